@@ -0,0 +1,73 @@
+//! Replays a memory trace recorded by the auto splitter and prints every
+//! split it causes along with the frame it happened on.
+//!
+//! ```text
+//! cargo replay [--running] <log file>
+//! ```
+//!
+//! By default the timer starts out not running, so the trace needs to
+//! include starting the run from the file select screen. Pass `--running`
+//! for traces that were started in the middle of a run. Every trace is
+//! replayed with the settings it was recorded with.
+
+use std::{env, fs, process};
+
+use minish_cap_auto_splitter::trace::{Replay, ReplayError};
+
+fn main() {
+    let mut running = false;
+    let mut path = None;
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--running" => running = true,
+            _ if path.is_none() => path = Some(arg),
+            _ => usage(),
+        }
+    }
+    let Some(path) = path else { usage() };
+
+    let log = fs::read_to_string(&path).unwrap_or_else(|e| {
+        eprintln!("Failed to read {path}: {e}");
+        process::exit(1);
+    });
+
+    let mut replay = Replay::new(running);
+
+    for (line_index, line) in log.lines().enumerate() {
+        match replay.feed(line) {
+            Ok(Some((name, frame))) => println!(
+                "{frame:>8} {:>12.3}s {name}",
+                replay.game_time().as_seconds_f64(),
+            ),
+            Ok(None) => {}
+            Err(ReplayError::Malformed) => {
+                eprintln!("Skipping malformed trace on line {}", line_index + 1)
+            }
+            Err(ReplayError::UnknownSetting) => {
+                eprintln!(
+                    "The trace on line {} was recorded with a setting that doesn't exist \
+                     anymore, skipping it.",
+                    line_index + 1,
+                )
+            }
+            Err(ReplayError::Missing(address)) => {
+                eprintln!(
+                    "The trace on line {} doesn't have the variable at {address:X}, \
+                     it needs to be recorded again.",
+                    line_index + 1,
+                );
+                process::exit(1);
+            }
+        }
+    }
+
+    if !replay.has_started() {
+        eprintln!("{path} doesn't contain a memory trace.");
+        process::exit(1);
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: replay [--running] <log file>");
+    process::exit(1);
+}
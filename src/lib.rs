@@ -1,153 +1,330 @@
-#![no_std]
-
-use arrayvec::ArrayString;
-use asr::{
-    gba, itoa,
-    time_util::frame_count,
-    timer::{self, TimerState},
-    watcher::Pair,
-};
+#![cfg_attr(target_arch = "wasm32", no_std)]
+
+use arrayvec::{ArrayString, ArrayVec};
+use asr::{gba, itoa, time::Duration, time_util::frame_count, timer::TimerState, watcher::Pair};
 use bytemuck::{Pod, Zeroable};
+use core::fmt::Write;
+use memory::Memory;
+use runtime::Runtime;
 use spinning_top::{const_spinlock, Spinlock};
 
+#[cfg(not(target_arch = "wasm32"))]
+extern crate alloc;
+
+pub mod memory;
+pub mod runtime;
+#[cfg(all(feature = "savestates", not(target_arch = "wasm32")))]
+pub mod savestate;
+#[cfg(test)]
+mod tests;
+pub mod trace;
+
 #[cfg(all(not(test), target_arch = "wasm32"))]
 #[panic_handler]
 fn panic(_: &core::panic::PanicInfo) -> ! {
     core::arch::wasm32::unreachable()
 }
 
+// Only the auto splitter's entry point, which is only compiled for wasm, uses
+// the state and the cartridge header.
+#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
 static STATE: Spinlock<State> = const_spinlock(State {
     game: None,
     settings: None,
+    rejected: None,
 });
 
-#[derive(asr::Settings)]
-struct Settings {
-    /// Get Smith's Sword
-    #[default = true]
-    get_smiths_sword: bool,
-    /// Receive Minish Cap
-    #[default = true]
-    receive_minish_cap: bool,
-    /// Enter Deepwood Shrine
-    #[default = true]
-    enter_deepwood_shrine: bool,
-    /// Get Gust Jar
-    #[default = true]
-    get_gust_jar: bool,
-    /// Enter Deepwood Shrine Boss Room
-    #[default = true]
-    enter_deepwood_shrine_boss_room: bool,
-    /// Get Earth Element
-    #[default = true]
-    get_earth_element: bool,
-    /// Enter Mt. Crenel
-    #[default = true]
-    enter_mt_crenel: bool,
-    /// Get Grip Ring
-    #[default = true]
-    get_grip_ring: bool,
-    /// Enter Cave of Flames
-    #[default = true]
-    enter_cave_of_flames: bool,
-    /// Get Cane of Pacci
-    #[default = true]
-    get_cane_of_pacci: bool,
-    /// Enter Cave of Flames Boss Room
-    #[default = true]
-    enter_cave_of_flames_boss_room: bool,
-    /// Get Fire Element
-    #[default = true]
-    get_fire_element: bool,
-    /// Get Pegasus Boots
-    #[default = true]
-    get_pegasus_boots: bool,
-    /// Get Bow
-    #[default = true]
-    get_bow: bool,
-    /// Enter Fortress of Winds
-    #[default = true]
-    enter_fortress_of_winds: bool,
-    /// Get Mole Mitts
-    #[default = true]
-    get_mole_mitts: bool,
-    /// Enter Fortress of Winds Boss Room
-    #[default = true]
-    enter_fortress_of_winds_boss_room: bool,
-    /// Get Ocarina
-    #[default = true]
-    get_ocarina: bool,
-    /// Get Magical Boomerang
-    #[default = true]
-    get_magical_boomerang: bool,
-    /// Get Power Bracelets
-    #[default = true]
-    get_power_bracelets: bool,
-    /// Get Flippers
-    #[default = true]
-    get_flippers: bool,
-    /// Enter Temple of Droplets
-    #[default = true]
-    enter_temple_of_droplets: bool,
-    /// Get Flame Lantern
-    #[default = true]
-    get_flame_lantern: bool,
-    // /// Enter Octo
-    // #[default = true]
-    // enter_octo: bool,
-    /// Get Water Element
-    #[default = true]
-    get_water_element: bool,
-    /// Enter Palace of Winds
-    #[default = true]
-    enter_palace_of_winds: bool,
-    /// Get Roc's Cape
-    #[default = true]
-    get_rocs_cape: bool,
-    // /// Enter Gyorg
-    // #[default = true]
-    // enter_gyorg: bool,
-    /// Get Wind Element
-    #[default = true]
-    get_wind_element: bool,
-    /// Get Four Sword
-    #[default = true]
-    get_four_sword: bool,
-    // /// Enter DHC
-    // #[default = true]
-    // enter_dhc: bool,
-    // /// 2nd Key in DHC
-    // #[default = true]
-    // second_key_in_dhc: bool,
-    // /// Black Knight
-    // #[default = true]
-    // black_knight: bool,
-    /// Get DHC Big Key
-    #[default = true]
-    get_dhc_big_key: bool,
-    // /// Darknuts
-    // #[default = true]
-    // darknuts: bool,
-    // /// Vaati 1
-    // #[default = true]
-    // vaati_1: bool,
-    // /// Vaati 2
-    // #[default = true]
-    // vaati_2: bool,
-    /// Defeat Vaati
-    #[default = true]
-    defeat_vaati: bool,
+/// Declares the settings. Outside of an auto splitting runtime there is
+/// nothing to register them with, so their defaults also make up
+/// `Settings::default`, from the same list. Memory traces list the settings
+/// that aren't at their defaults by their keys, so a replay runs with the
+/// same settings.
+macro_rules! settings {
+    (
+        $(#[$attr:meta])*
+        pub struct $name:ident {
+            $(
+                $(#[doc = $doc:literal])*
+                #[default = $default:literal]
+                $field:ident: bool,
+            )*
+        }
+    ) => {
+        $(#[$attr])*
+        pub struct $name {
+            $(
+                $(#[doc = $doc])*
+                #[default = $default]
+                $field: bool,
+            )*
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        impl Default for $name {
+            fn default() -> Self {
+                Self {
+                    $($field: $default,)*
+                }
+            }
+        }
+
+        impl $name {
+            /// Calls `f` with the key and the value of every setting that
+            /// isn't at its default.
+            fn for_each_changed(&self, mut f: impl FnMut(&'static str, bool)) {
+                $(
+                    if self.$field != $default {
+                        f(stringify!($field), self.$field);
+                    }
+                )*
+            }
+
+            /// Sets the setting with the given key. Returns [`None`] if there
+            /// is no such setting.
+            #[cfg(not(target_arch = "wasm32"))]
+            fn set(&mut self, key: &str, value: bool) -> Option<()> {
+                match key {
+                    $(stringify!($field) => self.$field = value,)*
+                    _ => return None,
+                }
+                Some(())
+            }
+        }
+    };
+}
+
+settings! {
+    #[derive(asr::Settings)]
+    #[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+    pub struct Settings {
+        /// Get Smith's Sword
+        #[default = true]
+        get_smiths_sword: bool,
+        // The runtime only has checkboxes, so every offset in frames that a
+        // delayed split can have gets its own. See `earliest_ticked`.
+        /// Receive Minish Cap (0 frames)
+        #[default = false]
+        receive_minish_cap_at_0_frames: bool,
+        /// Receive Minish Cap (10 frames)
+        #[default = false]
+        receive_minish_cap_at_10_frames: bool,
+        /// Receive Minish Cap (20 frames)
+        #[default = true]
+        receive_minish_cap: bool,
+        /// Receive Minish Cap (30 frames)
+        #[default = false]
+        receive_minish_cap_at_30_frames: bool,
+        /// Receive Minish Cap (40 frames)
+        #[default = false]
+        receive_minish_cap_at_40_frames: bool,
+        /// Receive Minish Cap (60 frames)
+        #[default = false]
+        receive_minish_cap_at_60_frames: bool,
+        /// Enter Deepwood Shrine
+        #[default = true]
+        enter_deepwood_shrine: bool,
+        /// Get Gust Jar
+        #[default = true]
+        get_gust_jar: bool,
+        /// Enter Deepwood Shrine Boss Room
+        #[default = true]
+        enter_deepwood_shrine_boss_room: bool,
+        /// Get Earth Element
+        #[default = true]
+        get_earth_element: bool,
+        /// Enter Mt. Crenel
+        #[default = true]
+        enter_mt_crenel: bool,
+        /// Get Grip Ring
+        #[default = true]
+        get_grip_ring: bool,
+        /// Enter Cave of Flames
+        #[default = true]
+        enter_cave_of_flames: bool,
+        /// Get Cane of Pacci
+        #[default = true]
+        get_cane_of_pacci: bool,
+        /// Enter Cave of Flames Boss Room
+        #[default = true]
+        enter_cave_of_flames_boss_room: bool,
+        /// Get Fire Element
+        #[default = true]
+        get_fire_element: bool,
+        /// Get Pegasus Boots
+        #[default = true]
+        get_pegasus_boots: bool,
+        /// Get Bow
+        #[default = true]
+        get_bow: bool,
+        /// Enter Fortress of Winds
+        #[default = true]
+        enter_fortress_of_winds: bool,
+        /// Get Mole Mitts
+        #[default = true]
+        get_mole_mitts: bool,
+        /// Enter Fortress of Winds Boss Room
+        #[default = true]
+        enter_fortress_of_winds_boss_room: bool,
+        /// Get Ocarina
+        #[default = true]
+        get_ocarina: bool,
+        /// Get Magical Boomerang
+        #[default = true]
+        get_magical_boomerang: bool,
+        /// Get Power Bracelets
+        #[default = true]
+        get_power_bracelets: bool,
+        /// Get Flippers
+        #[default = true]
+        get_flippers: bool,
+        /// Enter Temple of Droplets
+        #[default = true]
+        enter_temple_of_droplets: bool,
+        /// Get Flame Lantern
+        #[default = true]
+        get_flame_lantern: bool,
+        // /// Enter Octo
+        // #[default = true]
+        // enter_octo: bool,
+        /// Get Water Element
+        #[default = true]
+        get_water_element: bool,
+        /// Enter Palace of Winds
+        #[default = true]
+        enter_palace_of_winds: bool,
+        /// Get Roc's Cape
+        #[default = true]
+        get_rocs_cape: bool,
+        // /// Enter Gyorg
+        // #[default = true]
+        // enter_gyorg: bool,
+        /// Get Wind Element
+        #[default = true]
+        get_wind_element: bool,
+        /// Get Four Sword (0 frames)
+        #[default = false]
+        get_four_sword_at_0_frames: bool,
+        /// Get Four Sword (60 frames)
+        #[default = false]
+        get_four_sword_at_60_frames: bool,
+        /// Get Four Sword (120 frames)
+        #[default = false]
+        get_four_sword_at_120_frames: bool,
+        /// Get Four Sword (180 frames)
+        #[default = false]
+        get_four_sword_at_180_frames: bool,
+        /// Get Four Sword (244 frames)
+        #[default = true]
+        get_four_sword: bool,
+        /// Get Four Sword (300 frames)
+        #[default = false]
+        get_four_sword_at_300_frames: bool,
+        /// Enter DHC
+        #[default = false]
+        enter_dhc: bool,
+        // /// 2nd Key in DHC
+        // #[default = true]
+        // second_key_in_dhc: bool,
+        // /// Black Knight
+        // #[default = true]
+        // black_knight: bool,
+        /// Get DHC Big Key
+        #[default = true]
+        get_dhc_big_key: bool,
+        // /// Darknuts
+        // #[default = true]
+        // darknuts: bool,
+        // /// Vaati 1
+        // #[default = true]
+        // vaati_1: bool,
+        // /// Vaati 2
+        // #[default = true]
+        // vaati_2: bool,
+        /// Defeat Vaati
+        #[default = true]
+        defeat_vaati: bool,
+        // The game time comes from the frame counter unless the playtime of the
+        // save file is used instead. Timing by real time only wins over both.
+        /// Game time from playtime
+        #[default = false]
+        game_time_from_playtime: bool,
+        /// No game time
+        #[default = false]
+        no_game_time: bool,
+        // The GBA refreshes at ~59.73 Hz rather than 60 Hz.
+        /// GBA frame rate
+        #[default = false]
+        gba_frame_rate: bool,
+        // The frame counter jumps backwards when a save file or a savestate is
+        // loaded. Subtracting the jump wins over pausing for it.
+        /// Subtract frame counter jumps
+        #[default = false]
+        subtract_frame_counter_jumps: bool,
+        /// Pause on frame counter jumps
+        #[default = false]
+        pause_on_frame_counter_jumps: bool,
+        // Going back to the title screen any other way counts as well.
+        /// Reset on soft reset
+        #[default = false]
+        reset_on_soft_reset: bool,
+        /// Record a memory trace to the log
+        #[default = false]
+        record_memory_trace: bool,
+    }
+}
+
+impl Settings {
+    /// How many frames after the Minish Cap sprite shows up Receive Minish Cap
+    /// splits, if at all.
+    fn receive_minish_cap_offset(&self) -> Option<i64> {
+        earliest_ticked(&[
+            (0, self.receive_minish_cap_at_0_frames),
+            (10, self.receive_minish_cap_at_10_frames),
+            (RECEIVE_MINISH_CAP_DELAY, self.receive_minish_cap),
+            (30, self.receive_minish_cap_at_30_frames),
+            (40, self.receive_minish_cap_at_40_frames),
+            (60, self.receive_minish_cap_at_60_frames),
+        ])
+    }
+
+    /// How many frames after the Four Sword is in the inventory Get Four Sword
+    /// splits, if at all.
+    fn get_four_sword_offset(&self) -> Option<i64> {
+        earliest_ticked(&[
+            (0, self.get_four_sword_at_0_frames),
+            (60, self.get_four_sword_at_60_frames),
+            (120, self.get_four_sword_at_120_frames),
+            (180, self.get_four_sword_at_180_frames),
+            (GET_FOUR_SWORD_DELAY, self.get_four_sword),
+            (300, self.get_four_sword_at_300_frames),
+        ])
+    }
 }
 
+/// The earliest of the offsets whose setting is ticked. A delayed split
+/// happens only once, at that offset, and not at all when none is ticked.
+fn earliest_ticked(offsets: &[(i64, bool)]) -> Option<i64> {
+    offsets
+        .iter()
+        .find(|&&(_, ticked)| ticked)
+        .map(|&(offset, _)| offset)
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
 struct State {
-    game: Option<Game>,
+    game: Option<Game<gba::Emulator>>,
     settings: Option<Settings>,
+    // The emulator running an unsupported ROM, along with its header, so we
+    // neither attach to it again every tick nor complain about it again.
+    rejected: Option<(gba::Emulator, CartridgeHeader)>,
 }
 
-struct Game {
-    emulator: gba::Emulator,
+pub struct Game<M> {
+    memory: M,
+    region: Region,
     pause_menu: Watcher<PauseMenu>,
     scene: Watcher<Scene>,
+    task: Watcher<Task>,
     dhc_big_key: Watcher<i32>,
     vaati3_phases: Watcher<i32>,
     sprite: Watcher<Sprite>,
@@ -162,14 +339,19 @@ struct Game {
     tiger_scrolls: Watcher<u8>,
     mysterious_shells: Watcher<u16>,
     bombs: Watcher<u8>,
+    playtime: Watcher<u32>,
     accumulated_frame_count: i64,
-    delayed_split: Option<(&'static str, i64)>,
+    paused_frame_count: Option<i64>,
+    delayed_splits: ArrayVec<(&'static str, i64), MAX_DELAYED_SPLITS>,
     run_progress: RunProgress,
+    split_history: ArrayVec<RunProgress, MAX_SPLITS>,
+    recording_trace: bool,
 }
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 struct RunProgress {
-    smiths_sword: bool,
+    // Everything that was in the pause menu at some point during the run.
+    collected: PauseMenu,
     deepwood_shrine: bool,
     deepwood_shrine_boss: bool,
     mt_crenel: bool,
@@ -179,60 +361,213 @@ struct RunProgress {
     fortress_of_winds_boss: bool,
     temple_of_droplets: bool,
     palace_of_winds: bool,
+    dark_hyrule_castle: bool,
 }
 
-impl Game {
-    fn new_ntscj(emulator: gba::Emulator) -> Self {
+impl<M: Memory> Game<M> {
+    pub fn new(memory: M, region: Region) -> Self {
+        let addresses = region.addresses();
         Self {
-            emulator,
-            pause_menu: Watcher::new(0x2002B32),
-            scene: Watcher::new(0x3000BF4),
-            dhc_big_key: Watcher::new(0x2002EB2),
-            vaati3_phases: Watcher::new(0x30017BC),
-            sprite: Watcher::new(0x300116C),
-            frame_count: Watcher::new(0x300100C),
-            uix_position: Watcher::new(0x3001E4E),
-            uiy_position: Watcher::new(0x300187A),
-            link_position_y: Watcher::new(0x30010BE),
-            visual_rupees: Watcher::new(0x200AF0E),
-            visual_hearts: Watcher::new(0x200AF03),
-            visual_keys: Watcher::new(0x200AF12),
-            tiger_scrolls: Watcher::new(0x2002B44),
-            mysterious_shells: Watcher::new(0x2002B02),
-            bombs: Watcher::new(0x2002AEC),
+            memory,
+            region,
+            pause_menu: Watcher::new(addresses.pause_menu),
+            scene: Watcher::new(addresses.scene),
+            task: Watcher::new(addresses.task),
+            dhc_big_key: Watcher::new(addresses.dhc_big_key),
+            vaati3_phases: Watcher::new(addresses.vaati3_phases),
+            sprite: Watcher::new(addresses.sprite),
+            frame_count: Watcher::new(addresses.frame_count),
+            uix_position: Watcher::new(addresses.uix_position),
+            uiy_position: Watcher::new(addresses.uiy_position),
+            link_position_y: Watcher::new(addresses.link_position_y),
+            visual_rupees: Watcher::new(addresses.visual_rupees),
+            visual_hearts: Watcher::new(addresses.visual_hearts),
+            visual_keys: Watcher::new(addresses.visual_keys),
+            tiger_scrolls: Watcher::new(addresses.tiger_scrolls),
+            mysterious_shells: Watcher::new(addresses.mysterious_shells),
+            bombs: Watcher::new(addresses.bombs),
+            playtime: Watcher::new(addresses.playtime),
             accumulated_frame_count: 0,
-            delayed_split: None,
+            paused_frame_count: None,
+            delayed_splits: ArrayVec::new(),
             run_progress: Default::default(),
+            split_history: ArrayVec::new(),
+            recording_trace: false,
         }
     }
 
+    pub fn memory_mut(&mut self) -> &mut M {
+        &mut self.memory
+    }
+
     fn update_vars(&mut self) -> Option<Vars<'_>> {
         Some(Vars {
-            pause_menu: self.pause_menu.update(&self.emulator)?,
-            scene: self.scene.update(&self.emulator)?,
-            dhc_big_key: self.dhc_big_key.update(&self.emulator)?,
-            vaati3_phases: self.vaati3_phases.update(&self.emulator)?,
-            sprite: self.sprite.update(&self.emulator)?,
-            frame_count: self.frame_count.update(&self.emulator)?,
-            uix_position: self.uix_position.update(&self.emulator)?,
-            uiy_position: self.uiy_position.update(&self.emulator)?,
-            link_position_y: self.link_position_y.update(&self.emulator)?,
-            visual_rupees: self.visual_rupees.update(&self.emulator)?,
-            visual_hearts: self.visual_hearts.update(&self.emulator)?,
-            visual_keys: self.visual_keys.update(&self.emulator)?,
-            tiger_scrolls: self.tiger_scrolls.update(&self.emulator)?,
-            mysterious_shells: self.mysterious_shells.update(&self.emulator)?,
-            bombs: self.bombs.update(&self.emulator)?,
+            pause_menu: self.pause_menu.update(&self.memory)?,
+            scene: self.scene.update(&self.memory)?,
+            task: self.task.update(&self.memory)?,
+            dhc_big_key: self.dhc_big_key.update(&self.memory)?,
+            vaati3_phases: self.vaati3_phases.update(&self.memory)?,
+            sprite: self.sprite.update(&self.memory)?,
+            frame_count: self.frame_count.update(&self.memory)?,
+            uix_position: self.uix_position.update(&self.memory)?,
+            uiy_position: self.uiy_position.update(&self.memory)?,
+            link_position_y: self.link_position_y.update(&self.memory)?,
+            visual_rupees: self.visual_rupees.update(&self.memory)?,
+            visual_hearts: self.visual_hearts.update(&self.memory)?,
+            visual_keys: self.visual_keys.update(&self.memory)?,
+            tiger_scrolls: self.tiger_scrolls.update(&self.memory)?,
+            mysterious_shells: self.mysterious_shells.update(&self.memory)?,
+            bombs: self.bombs.update(&self.memory)?,
+            playtime: self.playtime.update(&self.memory)?,
             accumulated_frame_count: &mut self.accumulated_frame_count,
-            delayed_split: &mut self.delayed_split,
+            paused_frame_count: &mut self.paused_frame_count,
+            delayed_splits: &mut self.delayed_splits,
             run_progress: &mut self.run_progress,
+            split_history: &mut self.split_history,
+            recording_trace: &mut self.recording_trace,
         })
     }
 }
 
+/// The most the frame counter can advance between two ticks. Anything more
+/// than that is a jump rather than the game running.
+const MAX_FRAME_COUNTER_STEP: u16 = 0x1000;
+
+/// How many frames Receive Minish Cap waits by default.
+const RECEIVE_MINISH_CAP_DELAY: i64 = 20;
+
+/// How many frames Get Four Sword waits by default.
+const GET_FOUR_SWORD_DELAY: i64 = 244;
+
+/// The most splits that can wait for their frame at the same time.
+const MAX_DELAYED_SPLITS: usize = 4;
+
+/// The most splits the run progress can be rolled back through.
+const MAX_SPLITS: usize = 128;
+
+/// The GBA's actual refresh rate in Hz.
+const GBA_FRAME_RATE: f64 = 16_777_216.0 / 280_896.0;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Region {
+    NtscJ,
+}
+
+impl Region {
+    fn from_game_code(game_code: &[u8; 4]) -> Option<Self> {
+        match game_code {
+            b"BZMJ" => Some(Region::NtscJ),
+            _ => None,
+        }
+    }
+
+    fn game_code(self) -> &'static str {
+        match self {
+            Region::NtscJ => "BZMJ",
+        }
+    }
+
+    fn addresses(self) -> &'static Addresses {
+        match self {
+            Region::NtscJ => &Addresses::JAPAN,
+        }
+    }
+}
+
+/// The time the given number of frames take. There is no 50 Hz mode on the
+/// GBA, so every release runs at the same rate and is timed the same way.
+fn time_of_frames(frames: i64, gba_frame_rate: bool) -> Duration {
+    if gba_frame_rate {
+        Duration::seconds_f64(frames as f64 / GBA_FRAME_RATE)
+    } else {
+        frame_count::<60>(frames as u64)
+    }
+}
+
+#[derive(Copy, Clone, Pod, Zeroable, PartialEq, Eq)]
+#[repr(C)]
+struct CartridgeHeader {
+    title: [u8; 12],
+    game_code: [u8; 4],
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+impl CartridgeHeader {
+    const ADDRESS: u32 = 0x80000A0;
+    const TITLE: [u8; 12] = *b"GBAZELDA MC\0";
+
+    fn region(&self) -> Option<Region> {
+        if self.title != Self::TITLE {
+            return None;
+        }
+        Region::from_game_code(&self.game_code)
+    }
+
+    fn print_unsupported(&self) {
+        let mut message = ArrayString::<128>::new();
+        message.push_str("Unsupported ROM \"");
+        message.push_str(
+            core::str::from_utf8(&self.title)
+                .unwrap_or_default()
+                .trim_end_matches('\0'),
+        );
+        message.push_str("\" (");
+        message.push_str(core::str::from_utf8(&self.game_code).unwrap_or("????"));
+        message.push_str("), only BZMJ is supported.");
+        asr::print_message(&message);
+    }
+}
+
+/// The addresses of all the watched variables for a single release of the
+/// game.
+struct Addresses {
+    pause_menu: u32,
+    scene: u32,
+    task: u32,
+    dhc_big_key: u32,
+    vaati3_phases: u32,
+    sprite: u32,
+    frame_count: u32,
+    uix_position: u32,
+    uiy_position: u32,
+    link_position_y: u32,
+    visual_rupees: u32,
+    visual_hearts: u32,
+    visual_keys: u32,
+    tiger_scrolls: u32,
+    mysterious_shells: u32,
+    bombs: u32,
+    playtime: u32,
+}
+
+impl Addresses {
+    /// The addresses of the Japanese release. The USA release (BZME) isn't
+    /// supported until its addresses are checked against the game.
+    const JAPAN: Self = Self {
+        pause_menu: 0x2002B32,
+        scene: 0x3000BF4,
+        task: 0x3001002,
+        dhc_big_key: 0x2002EB2,
+        vaati3_phases: 0x30017BC,
+        sprite: 0x300116C,
+        frame_count: 0x300100C,
+        uix_position: 0x3001E4E,
+        uiy_position: 0x300187A,
+        link_position_y: 0x30010BE,
+        visual_rupees: 0x200AF0E,
+        visual_hearts: 0x200AF03,
+        visual_keys: 0x200AF12,
+        tiger_scrolls: 0x2002B44,
+        mysterious_shells: 0x2002B02,
+        bombs: 0x2002AEC,
+        playtime: 0x2002AE0,
+    };
+}
+
 struct Vars<'a> {
     pause_menu: &'a Pair<PauseMenu>,
     scene: &'a Pair<Scene>,
+    task: &'a Pair<Task>,
     dhc_big_key: &'a Pair<i32>,
     vaati3_phases: &'a Pair<i32>,
     sprite: &'a Pair<Sprite>,
@@ -247,15 +582,207 @@ struct Vars<'a> {
     tiger_scrolls: &'a Pair<u8>,
     mysterious_shells: &'a Pair<u16>,
     bombs: &'a Pair<u8>,
+    // The playtime of the save file, in frames.
+    playtime: &'a Pair<u32>,
     accumulated_frame_count: &'a mut i64,
-    delayed_split: &'a mut Option<(&'static str, i64)>,
+    // The frame count the game time is paused at after the frame counter
+    // jumped backwards.
+    paused_frame_count: &'a mut Option<i64>,
+    // The splits that are waiting for their frame, ordered by it.
+    delayed_splits: &'a mut ArrayVec<(&'static str, i64), MAX_DELAYED_SPLITS>,
     run_progress: &'a mut RunProgress,
+    // The run progress from before each of the splits so far.
+    split_history: &'a mut ArrayVec<RunProgress, MAX_SPLITS>,
+    // Whether a trace was started by an earlier tick.
+    recording_trace: &'a mut bool,
 }
 
 impl Vars<'_> {
     fn frame_count(&self) -> i64 {
         *self.accumulated_frame_count + self.frame_count.current as i64
     }
+
+    /// The frame count the game time is at, which lags behind the frame count
+    /// while it's paused.
+    fn game_time_frame_count(&self) -> i64 {
+        self.paused_frame_count.unwrap_or(self.frame_count())
+    }
+
+    /// Whether the game went back to the title screen or the file select
+    /// screen, such as when it's soft reset.
+    fn returned_to_title(&self) -> bool {
+        self.task
+            .check(|&task| task == Task::TITLE || task == Task::FILE_SELECT)
+    }
+
+    /// Whether Link just entered the scene. Only the change counts, so a split
+    /// that is undone while Link is still in there waits for the scene to be
+    /// entered again.
+    fn entered(&self, scene: Scene) -> bool {
+        self.scene.check(|&current| current == scene)
+    }
+
+    /// Remembers everything that was in the pause menu before, so it's only
+    /// split for once, even when it comes back from loading a save file. While
+    /// the game isn't running, such as on the file select screen, the save
+    /// file is loaded, so everything in it counts as collected as well.
+    fn remember_collected(&mut self) {
+        let collected = &mut self.run_progress.collected;
+        collected.add(&self.pause_menu.old);
+        if self.task.old != Task::GAME || self.task.current != Task::GAME {
+            collected.add(&self.pause_menu.current);
+        }
+    }
+
+    /// Whether `has` just became true for the pause menu, for the first time
+    /// during the run.
+    fn collected(&self, has: impl Fn(&PauseMenu) -> bool) -> bool {
+        self.pause_menu.check(&has) && !has(&self.run_progress.collected)
+    }
+
+    /// Brings the run progress in line with the timer's current split, so
+    /// undoing a split makes it possible to split for it again.
+    fn sync_split_index(&mut self, split_index: usize) {
+        while self.split_history.len() > split_index {
+            if let Some(progress) = self.split_history.pop() {
+                *self.run_progress = progress;
+            }
+        }
+        // Skipped splits didn't change the run progress.
+        while self.split_history.len() < split_index {
+            if self
+                .split_history
+                .try_push(self.run_progress.clone())
+                .is_err()
+            {
+                break;
+            }
+        }
+    }
+
+    /// Splits the given number of frames from now. Unless that's right away,
+    /// the split gets queued up, and a split that is already queued up keeps
+    /// the frame it was queued up for.
+    fn split_after(&mut self, name: &'static str, delay: i64) -> Option<&'static str> {
+        if delay <= 0 {
+            return Some(name);
+        }
+        let frame = self.game_time_frame_count() + delay;
+        if self
+            .delayed_splits
+            .iter()
+            .any(|&(queued, _)| queued == name)
+        {
+            return None;
+        }
+        let index = self
+            .delayed_splits
+            .iter()
+            .position(|&(_, queued_frame)| queued_frame > frame)
+            .unwrap_or(self.delayed_splits.len());
+        let _ = self.delayed_splits.try_insert(index, (name, frame));
+        None
+    }
+
+    /// Takes the next delayed split out of the queue, if its frame has come.
+    fn due_delayed_split(&mut self) -> Option<(&'static str, i64)> {
+        let &(name, frame) = self.delayed_splits.first()?;
+        if self.game_time_frame_count() < frame {
+            return None;
+        }
+        self.delayed_splits.remove(0);
+        Some((name, frame))
+    }
+
+    /// Forgets everything about the current run.
+    fn clear_run(&mut self) {
+        *self.run_progress = Default::default();
+        self.split_history.clear();
+        self.delayed_splits.clear();
+        *self.paused_frame_count = None;
+    }
+
+    /// The game time of the given frame, measured the way the settings say.
+    /// Not setting the game time wins over the playtime.
+    fn game_time(&self, settings: &Settings, frame: i64) -> Option<Duration> {
+        if settings.no_game_time {
+            return None;
+        }
+        let frames = if settings.game_time_from_playtime {
+            // The playtime counts the same frames, so it's as far behind as
+            // the frame is.
+            self.playtime.current as i64 - (self.game_time_frame_count() - frame)
+        } else {
+            frame
+        };
+        Some(time_of_frames(frames.max(0), settings.gba_frame_rate))
+    }
+
+    /// Accumulates the frame counter into a frame count that keeps going when
+    /// the frame counter wraps around. Any other jump of the frame counter
+    /// comes from loading a save file, soft resetting or loading a savestate,
+    /// and is handled the way the settings say. Subtracting the jump wins over
+    /// pausing for it.
+    fn accumulate_frame_count(&mut self, settings: &Settings) {
+        let &Pair { old, current } = self.frame_count;
+        if current.wrapping_sub(old) <= MAX_FRAME_COUNTER_STEP {
+            if current < old {
+                *self.accumulated_frame_count += 0x10000;
+            }
+            if self
+                .paused_frame_count
+                .is_some_and(|paused| self.frame_count() >= paused)
+            {
+                *self.paused_frame_count = None;
+            }
+            return;
+        }
+
+        let jump = current as i64 - old as i64;
+        let follow_jump = jump < 0
+            && (settings.subtract_frame_counter_jumps || settings.pause_on_frame_counter_jumps);
+        if !follow_jump {
+            // Carry on as if the frame counter didn't jump.
+            *self.accumulated_frame_count -= jump;
+        } else if !settings.subtract_frame_counter_jumps {
+            // The frame count goes back along with the frame counter, but the
+            // game time waits for it to catch up.
+            let before = *self.accumulated_frame_count + old as i64;
+            *self.paused_frame_count =
+                Some(self.paused_frame_count.map_or(before, |p| p.max(before)));
+        }
+    }
+
+    /// Calls `f` with the address, the current value and whether it changed
+    /// for every watched variable.
+    fn for_each_watched(&self, addresses: &Addresses, mut f: impl FnMut(u32, &[u8], bool)) {
+        fn watched<T: Pod>(pair: &Pair<T>) -> (&[u8], bool) {
+            let current = bytemuck::bytes_of(&pair.current);
+            (current, current != bytemuck::bytes_of(&pair.old))
+        }
+        let watched_vars = [
+            (addresses.pause_menu, watched(self.pause_menu)),
+            (addresses.scene, watched(self.scene)),
+            (addresses.task, watched(self.task)),
+            (addresses.dhc_big_key, watched(self.dhc_big_key)),
+            (addresses.vaati3_phases, watched(self.vaati3_phases)),
+            (addresses.sprite, watched(self.sprite)),
+            (addresses.frame_count, watched(self.frame_count)),
+            (addresses.uix_position, watched(self.uix_position)),
+            (addresses.uiy_position, watched(self.uiy_position)),
+            (addresses.link_position_y, watched(self.link_position_y)),
+            (addresses.visual_rupees, watched(self.visual_rupees)),
+            (addresses.visual_hearts, watched(self.visual_hearts)),
+            (addresses.visual_keys, watched(self.visual_keys)),
+            (addresses.tiger_scrolls, watched(self.tiger_scrolls)),
+            (addresses.mysterious_shells, watched(self.mysterious_shells)),
+            (addresses.bombs, watched(self.bombs)),
+            (addresses.playtime, watched(self.playtime)),
+        ];
+        for (address, (current, changed)) in watched_vars {
+            f(address, current, changed);
+        }
+    }
 }
 
 struct Watcher<T> {
@@ -271,8 +798,8 @@ impl<T: Pod> Watcher<T> {
         }
     }
 
-    fn update(&mut self, emulator: &gba::Emulator) -> Option<&Pair<T>> {
-        self.watcher.update(emulator.read(self.address).ok())
+    fn update(&mut self, memory: &impl Memory) -> Option<&Pair<T>> {
+        self.watcher.update(memory.read(self.address))
     }
 }
 
@@ -285,10 +812,25 @@ struct PauseMenu {
     permanent_equipment: PermanentEquipment,
 }
 
+impl Default for PauseMenu {
+    fn default() -> Self {
+        Self::zeroed()
+    }
+}
+
 impl PauseMenu {
     fn has_item(&self, inventory_slot: usize, inventory_item: InventoryItem) -> bool {
         self.inventory[inventory_slot].contains(inventory_item)
     }
+
+    /// Adds everything in the other pause menu to this one.
+    fn add(&mut self, other: &PauseMenu) {
+        for (items, other_items) in self.inventory.iter_mut().zip(other.inventory) {
+            *items |= other_items;
+        }
+        self.elements |= other.elements;
+        self.permanent_equipment |= other.permanent_equipment;
+    }
 }
 
 bitflags::bitflags! {
@@ -351,7 +893,6 @@ struct Scene(u8);
 
 #[allow(unused)]
 impl Scene {
-    const TITLE_SCREEN: Self = Self(0);
     const MINISH_WOODS: Self = Self(0);
     const MINISH_VILLAGE: Self = Self(0x01);
     const MARKET_PLACE: Self = Self(0x02);
@@ -375,6 +916,22 @@ impl Scene {
     const VAATI3: Self = Self(0x8B);
 }
 
+/// What the game as a whole is doing. The title screen shares its scene with
+/// Minish Woods, so this is what tells them apart.
+#[derive(Copy, Clone, Pod, Zeroable, PartialEq, Eq)]
+#[repr(transparent)]
+struct Task(u8);
+
+#[allow(unused)]
+impl Task {
+    const TRANSITION: Self = Self(0);
+    const TITLE: Self = Self(1);
+    const FILE_SELECT: Self = Self(2);
+    const GAME: Self = Self(3);
+    const GAME_OVER: Self = Self(4);
+    const STAFF_ROLL: Self = Self(5);
+}
+
 #[derive(Copy, Clone, Pod, Zeroable, PartialEq, Eq)]
 #[repr(transparent)]
 struct Sprite(u16);
@@ -416,140 +973,215 @@ mod inventory_slot {
     pub const OCARINA: usize = 5;
 }
 
+#[cfg(target_arch = "wasm32")]
 #[no_mangle]
 pub extern "C" fn update() {
     let mut state = STATE.lock();
     let state = &mut *state;
     let settings = state.settings.get_or_insert_with(Settings::register);
     if state.game.is_none() {
-        state.game = gba::Emulator::attach().map(Game::new_ntscj);
+        let emulator = match state.rejected.take() {
+            Some((emulator, header)) if emulator.is_open() => Some((emulator, Some(header))),
+            _ => gba::Emulator::attach().map(|emulator| (emulator, None)),
+        };
+        if let Some((emulator, rejected_header)) = emulator {
+            match emulator.read::<CartridgeHeader>(CartridgeHeader::ADDRESS) {
+                Ok(header) => match header.region() {
+                    Some(region) => state.game = Some(Game::new(emulator, region)),
+                    None => {
+                        if rejected_header != Some(header) {
+                            header.print_unsupported();
+                        }
+                        state.rejected = Some((emulator, header));
+                    }
+                },
+                // Most emulators only have EWRAM and IWRAM mapped, not the
+                // ROM, so there is no header to go by. The game is read as
+                // the only release that is supported then.
+                Err(_) => {
+                    asr::print_message(
+                        "Couldn't read the cartridge header, so the game is assumed to be \
+                         BZMJ.",
+                    );
+                    state.game = Some(Game::new(emulator, Region::NtscJ));
+                }
+            }
+        }
     }
     if let Some(game) = &mut state.game {
-        if !game.emulator.is_open() {
+        if !game.memory.is_open() {
             state.game = None;
             return;
         }
-        if let Some(mut vars) = game.update_vars() {
-            let mut string = ArrayString::<8>::new();
-            let hearts = vars.visual_hearts.current;
-            if !(1..=3).contains(&hearts) {
-                // Skip the 0 if we show a fraction.
-                string.push_str(itoa::Buffer::new().format(hearts / 4));
-            }
-            match hearts % 4 {
-                1 => string.push('¼'),
-                2 => string.push('½'),
-                3 => string.push('¾'),
-                _ => {}
+        game.update(settings, &mut runtime::AsrRuntime);
+    }
+}
+
+impl<M: Memory> Game<M> {
+    /// Runs a single tick of the auto splitter, returning the split that
+    /// happened, if any, along with the frame it happened on.
+    pub fn update(
+        &mut self,
+        settings: &Settings,
+        runtime: &mut impl Runtime,
+    ) -> Option<(&'static str, i64)> {
+        let region = self.region;
+        let mut vars = self.update_vars()?;
+
+        // The trace only starts on a tick that could be read, so it never
+        // goes without its start.
+        if settings.record_memory_trace {
+            trace::record(&vars, region, settings, !*vars.recording_trace, runtime);
+        }
+        *vars.recording_trace = settings.record_memory_trace;
+
+        let mut string = ArrayString::<8>::new();
+        let hearts = vars.visual_hearts.current;
+        if !(1..=3).contains(&hearts) {
+            // Skip the 0 if we show a fraction.
+            string.push_str(itoa::Buffer::new().format(hearts / 4));
+        }
+        match hearts % 4 {
+            1 => string.push('¼'),
+            2 => string.push('½'),
+            3 => string.push('¾'),
+            _ => {}
+        }
+        runtime.set_variable("Hearts", &string);
+        runtime.set_variable_int("Rupees", vars.visual_rupees.current);
+        runtime.set_variable_int("Keys", vars.visual_keys.current);
+        runtime.set_variable_int("Tiger Scrolls", vars.tiger_scrolls.current);
+        runtime.set_variable_int("Mysterious Shells", vars.mysterious_shells.current);
+        runtime.set_variable_int("Bombs", vars.bombs.current);
+        let playtime = time_of_frames(vars.playtime.current as i64, settings.gba_frame_rate);
+        runtime.set_variable("Playtime", &format_time(playtime));
+
+        match runtime.state() {
+            TimerState::NotRunning
+                if vars.task.current == Task::FILE_SELECT
+                    && vars.uix_position.current == 24
+                    && vars.uiy_position.old == 144
+                    && vars.uiy_position.current > 144 =>
+            {
+                *vars.accumulated_frame_count = -(vars.frame_count.current as i64);
+                vars.clear_run();
+                runtime.start();
+                if !settings.no_game_time {
+                    runtime.pause_game_time();
+                }
             }
-            timer::set_variable("Hearts", &string);
-            timer::set_variable_int("Rupees", vars.visual_rupees.current);
-            timer::set_variable_int("Keys", vars.visual_keys.current);
-            timer::set_variable_int("Tiger Scrolls", vars.tiger_scrolls.current);
-            timer::set_variable_int("Mysterious Shells", vars.mysterious_shells.current);
-            timer::set_variable_int("Bombs", vars.bombs.current);
-
-            match timer::state() {
-                TimerState::NotRunning => {
-                    if vars.uix_position.current == 24
-                        && vars.uiy_position.old == 144
-                        && vars.uiy_position.current > 144
-                    {
-                        *vars.accumulated_frame_count = -(vars.frame_count.current as i64);
-                        *vars.run_progress = Default::default();
-                        timer::start();
-                        timer::pause_game_time();
-                    }
+            TimerState::Running | TimerState::Paused => {
+                if settings.reset_on_soft_reset && vars.returned_to_title() {
+                    vars.clear_run();
+                    runtime.reset();
+                    return None;
                 }
-                TimerState::Running | TimerState::Paused => {
-                    if vars.frame_count.current < vars.frame_count.old {
-                        *vars.accumulated_frame_count += vars.frame_count.old as i64 + 1;
-                    }
 
-                    timer::set_game_time(frame_count::<60>(vars.frame_count() as u64));
+                if let Some(split_index) = runtime.current_split_index() {
+                    vars.sync_split_index(split_index);
+                }
+                vars.accumulate_frame_count(settings);
+                if let Some(game_time) = vars.game_time(settings, vars.game_time_frame_count()) {
+                    runtime.set_game_time(game_time);
+                }
 
-                    if let Some(reason) = should_split(&mut vars, settings) {
-                        asr::print_message(reason);
-                        timer::split();
+                let progress = vars.run_progress.clone();
+                if let Some((reason, frame)) = should_split(&mut vars, settings) {
+                    let _ = vars.split_history.try_push(progress);
+                    // The tick can come a few frames after the split
+                    // happened, so the split gets the game time of its frame.
+                    if let Some(game_time) = vars.game_time(settings, frame) {
+                        runtime.set_game_time(game_time);
                     }
+                    runtime.print_message(reason);
+                    runtime.split();
+                    return Some((reason, frame));
                 }
-                _ => {}
             }
+            _ => {}
         }
+        None
     }
 }
 
-fn should_split(vars: &mut Vars, settings: &Settings) -> Option<&'static str> {
-    if let Some((message, time_stamp)) = *vars.delayed_split {
-        if vars.frame_count() >= time_stamp {
-            *vars.delayed_split = None;
-            return Some(message);
-        }
+/// Formats the time as h:mm:ss.ff.
+fn format_time(time: Duration) -> ArrayString<16> {
+    let seconds = time.whole_seconds();
+    let mut string = ArrayString::new();
+    let _ = write!(
+        string,
+        "{}:{:02}:{:02}.{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        time.subsec_milliseconds() / 10,
+    );
+    string
+}
+
+/// Returns the split that should happen, along with the frame it happened
+/// on.
+fn should_split(vars: &mut Vars, settings: &Settings) -> Option<(&'static str, i64)> {
+    vars.remember_collected();
+    if let Some(split) = vars.due_delayed_split() {
+        return Some(split);
     }
+    let frame = vars.game_time_frame_count();
+    detect_split(vars, settings).map(|name| (name, frame))
+}
+
+fn detect_split(vars: &mut Vars, settings: &Settings) -> Option<&'static str> {
     if vars
-        .pause_menu
-        .check(|menu| menu.has_item(inventory_slot::SMITHS_SWORD, InventoryItem::SMITHS_SWORD))
+        .collected(|menu| menu.has_item(inventory_slot::SMITHS_SWORD, InventoryItem::SMITHS_SWORD))
+        && settings.get_smiths_sword
     {
-        // Workaround to detect loading a save file.
-        if vars.run_progress.smiths_sword {
-            return None;
-        }
-
         // Get Smith's Sword
-        vars.run_progress.smiths_sword = true;
-        return settings.get_smiths_sword.then_some("Get Smith's Sword");
+        return Some("Get Smith's Sword");
     }
     if vars
         .sprite
         .check(|&sprite| sprite == Sprite::RECEIVE_MINISH_CAP)
         && vars.scene.current == Scene::MINISH_WOODS
-        && settings.receive_minish_cap
+        && vars.task.current == Task::GAME
     {
-        // Receive Minish Cap
-        *vars.delayed_split = Some(("Receive Minish Cap", vars.frame_count() + 20));
-        return None;
+        if let Some(offset) = settings.receive_minish_cap_offset() {
+            // Receive Minish Cap
+            return vars.split_after("Receive Minish Cap", offset);
+        }
     }
     if !vars.run_progress.deepwood_shrine
-        && vars.scene.current == Scene::DEEPWOOD_SHRINE
+        && vars.entered(Scene::DEEPWOOD_SHRINE)
         && settings.enter_deepwood_shrine
     {
         // Enter Deepwood Shrine
         vars.run_progress.deepwood_shrine = true;
         return Some("Enter Deepwood Shrine");
     }
-    if vars
-        .pause_menu
-        .check(|menu| menu.has_item(inventory_slot::GUST_JAR, InventoryItem::GUST_JAR))
+    if vars.collected(|menu| menu.has_item(inventory_slot::GUST_JAR, InventoryItem::GUST_JAR))
         && settings.get_gust_jar
     {
         // Get Gust Jar
         return Some("Get Gust Jar");
     }
     if !vars.run_progress.deepwood_shrine_boss
-        && vars.scene.current == Scene::DEEPWOOD_SHRINE_BOSS
+        && vars.entered(Scene::DEEPWOOD_SHRINE_BOSS)
         && settings.enter_deepwood_shrine_boss_room
     {
         // Enter Deepwood Shrine Boss Room
         vars.run_progress.deepwood_shrine_boss = true;
         return Some("Enter Deepwood Shrine Boss Room");
     }
-    if vars
-        .pause_menu
-        .check(|menu| menu.elements.contains(Elements::EARTH))
-        && settings.get_earth_element
+    if vars.collected(|menu| menu.elements.contains(Elements::EARTH)) && settings.get_earth_element
     {
         // Get Earth Element
         return Some("Get Earth Element");
     }
-    if !vars.run_progress.mt_crenel
-        && vars.scene.current == Scene::MT_CRENEL
-        && settings.enter_mt_crenel
-    {
+    if !vars.run_progress.mt_crenel && vars.entered(Scene::MT_CRENEL) && settings.enter_mt_crenel {
         // Enter Mt. Crenel
         vars.run_progress.mt_crenel = true;
         return Some("Enter Mt. Crenel");
     }
-    if vars.pause_menu.check(|menu| {
+    if vars.collected(|menu| {
         menu.permanent_equipment
             .contains(PermanentEquipment::GRIP_RING)
     }) && settings.get_grip_ring
@@ -558,70 +1190,62 @@ fn should_split(vars: &mut Vars, settings: &Settings) -> Option<&'static str> {
         return Some("Get Grip Ring");
     }
     if !vars.run_progress.cave_of_flames
-        && vars.scene.current == Scene::CAVE_OF_FLAMES
+        && vars.entered(Scene::CAVE_OF_FLAMES)
         && settings.enter_cave_of_flames
     {
         // Enter Cave of Flames
         vars.run_progress.cave_of_flames = true;
         return Some("Enter Cave of Flames");
     }
-    if vars
-        .pause_menu
-        .check(|menu| menu.has_item(inventory_slot::CANE_OF_PACCI, InventoryItem::CANE_OF_PACCI))
-        && settings.get_cane_of_pacci
+    if vars.collected(|menu| {
+        menu.has_item(inventory_slot::CANE_OF_PACCI, InventoryItem::CANE_OF_PACCI)
+    }) && settings.get_cane_of_pacci
     {
         // Get Cane of Pacci
         return Some("Get Cane of Pacci");
     }
     if !vars.run_progress.cave_of_flames_boss
-        && vars.scene.current == Scene::CAVE_OF_FLAMES_BOSS
+        && vars.entered(Scene::CAVE_OF_FLAMES_BOSS)
         && settings.enter_cave_of_flames_boss_room
     {
         // Enter Cave of Flames Boss Room
         vars.run_progress.cave_of_flames_boss = true;
         return Some("Enter Cave of Flames Boss Room");
     }
-    if vars
-        .pause_menu
-        .check(|menu| menu.elements.contains(Elements::FIRE))
-        && settings.get_fire_element
-    {
+    if vars.collected(|menu| menu.elements.contains(Elements::FIRE)) && settings.get_fire_element {
         // Get Fire Element
         return Some("Get Fire Element");
     }
-    if vars
-        .pause_menu
-        .check(|menu| menu.has_item(inventory_slot::PEGASUS_BOOTS, InventoryItem::PEGASUS_BOOTS))
-        && settings.get_pegasus_boots
+    if vars.collected(|menu| {
+        menu.has_item(inventory_slot::PEGASUS_BOOTS, InventoryItem::PEGASUS_BOOTS)
+    }) && settings.get_pegasus_boots
     {
         // Get Pegasus Boots
         return Some("Get Pegasus Boots");
     }
-    if vars
-        .pause_menu
-        .check(|menu| menu.has_item(inventory_slot::BOW, InventoryItem::BOW))
+    if vars.collected(|menu| menu.has_item(inventory_slot::BOW, InventoryItem::BOW))
         && settings.get_bow
     {
         // Get Bow
         return Some("Get Bow");
     }
     if !vars.run_progress.fortress_of_winds
-        && vars.scene.current == Scene::FORTRESS_OF_WINDS
+        && vars.entered(Scene::FORTRESS_OF_WINDS)
         && settings.enter_fortress_of_winds
     {
         // Enter Fortress of Winds
         vars.run_progress.fortress_of_winds = true;
         return Some("Enter Fortress of Winds");
     }
-    if vars
-        .pause_menu
-        .check(|menu| menu.has_item(inventory_slot::MOLE_MITTS, InventoryItem::MOLE_MITTS))
+    if vars.collected(|menu| menu.has_item(inventory_slot::MOLE_MITTS, InventoryItem::MOLE_MITTS))
         && settings.get_mole_mitts
     {
         // Get Mole Mitts
         return Some("Get Mole Mitts");
     }
     if !vars.run_progress.fortress_of_winds_boss
+        && (vars.scene.old != Scene::FORTRESS_OF_WINDS_GREEN_FLOOR
+            || vars.link_position_y.old > 1015)
         && vars.scene.current == Scene::FORTRESS_OF_WINDS_GREEN_FLOOR
         && vars.link_position_y.current <= 1015
         && settings.enter_fortress_of_winds_boss_room
@@ -630,15 +1254,13 @@ fn should_split(vars: &mut Vars, settings: &Settings) -> Option<&'static str> {
         vars.run_progress.fortress_of_winds_boss = true;
         return Some("Enter Fortress of Winds Boss Room");
     }
-    if vars
-        .pause_menu
-        .check(|menu| menu.has_item(inventory_slot::OCARINA, InventoryItem::OCARINA))
+    if vars.collected(|menu| menu.has_item(inventory_slot::OCARINA, InventoryItem::OCARINA))
         && settings.get_ocarina
     {
         // Get Ocarina
         return Some("Get Ocarina");
     }
-    if vars.pause_menu.check(|menu| {
+    if vars.collected(|menu| {
         menu.has_item(
             inventory_slot::MAGICAL_BOOMERANG,
             InventoryItem::MAGICAL_BOOMERANG,
@@ -648,7 +1270,7 @@ fn should_split(vars: &mut Vars, settings: &Settings) -> Option<&'static str> {
         // Get Magical Boomerang
         return Some("Get Magical Boomerang");
     }
-    if vars.pause_menu.check(|menu| {
+    if vars.collected(|menu| {
         menu.permanent_equipment
             .contains(PermanentEquipment::POWER_BRACELETS)
     }) && settings.get_power_bracelets
@@ -656,7 +1278,7 @@ fn should_split(vars: &mut Vars, settings: &Settings) -> Option<&'static str> {
         // Get Power Bracelets
         return Some("Get Power Bracelets");
     }
-    if vars.pause_menu.check(|menu| {
+    if vars.collected(|menu| {
         menu.permanent_equipment
             .contains(PermanentEquipment::FLIPPERS)
     }) && settings.get_flippers
@@ -665,65 +1287,59 @@ fn should_split(vars: &mut Vars, settings: &Settings) -> Option<&'static str> {
         return Some("Get Flippers");
     }
     if !vars.run_progress.temple_of_droplets
-        && vars.scene.current == Scene::TEMPLE_OF_DROPLETS
+        && vars.entered(Scene::TEMPLE_OF_DROPLETS)
         && settings.enter_temple_of_droplets
     {
         // Enter Temple of Droplets
         vars.run_progress.temple_of_droplets = true;
         return Some("Enter Temple of Droplets");
     }
-    if vars
-        .pause_menu
-        .check(|menu| menu.has_item(inventory_slot::FLAME_LANTERN, InventoryItem::FLAME_LANTERN))
-        && settings.get_flame_lantern
+    if vars.collected(|menu| {
+        menu.has_item(inventory_slot::FLAME_LANTERN, InventoryItem::FLAME_LANTERN)
+    }) && settings.get_flame_lantern
     {
         // Get Flame Lantern
         return Some("Get Flame Lantern");
     }
     // TODO: Enter Octo
-    if vars
-        .pause_menu
-        .check(|menu| menu.elements.contains(Elements::WATER))
-        && settings.get_water_element
+    if vars.collected(|menu| menu.elements.contains(Elements::WATER)) && settings.get_water_element
     {
         // Get Water Element
         return Some("Get Water Element");
     }
     if !vars.run_progress.palace_of_winds
-        && vars.scene.current == Scene::PALACE_OF_WINDS
+        && vars.entered(Scene::PALACE_OF_WINDS)
         && settings.enter_palace_of_winds
     {
         // Enter Palace of Winds
         vars.run_progress.palace_of_winds = true;
         return Some("Enter Palace of Winds");
     }
-    if vars
-        .pause_menu
-        .check(|menu| menu.has_item(inventory_slot::ROCS_CAPE, InventoryItem::ROCS_CAPE))
+    if vars.collected(|menu| menu.has_item(inventory_slot::ROCS_CAPE, InventoryItem::ROCS_CAPE))
         && settings.get_rocs_cape
     {
         // Get Roc's Cape
         return Some("Get Roc's Cape");
     }
     // TODO: Enter Gyorg
-    if vars
-        .pause_menu
-        .check(|menu| menu.elements.contains(Elements::WIND))
-        && settings.get_wind_element
-    {
+    if vars.collected(|menu| menu.elements.contains(Elements::WIND)) && settings.get_wind_element {
         // Get Wind Element
         return Some("Get Wind Element");
     }
-    if vars
-        .pause_menu
-        .check(|menu| menu.has_item(inventory_slot::FOUR_SWORD, InventoryItem::FOUR_SWORD))
-        && settings.get_four_sword
+    if vars.collected(|menu| menu.has_item(inventory_slot::FOUR_SWORD, InventoryItem::FOUR_SWORD)) {
+        if let Some(offset) = settings.get_four_sword_offset() {
+            // Get Four Sword
+            return vars.split_after("Get Four Sword", offset);
+        }
+    }
+    if !vars.run_progress.dark_hyrule_castle
+        && vars.entered(Scene::DARK_HYRULE_CASTLE)
+        && settings.enter_dhc
     {
-        // Get Four Sword
-        *vars.delayed_split = Some(("Get Four Sword", vars.frame_count() + 244));
-        return None;
+        // Enter DHC
+        vars.run_progress.dark_hyrule_castle = true;
+        return Some("Enter DHC");
     }
-    // TODO: Enter DHC
     // TODO: 2nd Key in DHC
     // TODO: Black Knight
     if vars.dhc_big_key.check(|&v| v & 4 != 0) && settings.get_dhc_big_key {
@@ -0,0 +1,85 @@
+#[cfg(not(target_arch = "wasm32"))]
+use alloc::{boxed::Box, vec};
+use asr::gba;
+use bytemuck::Pod;
+
+/// Something the game's memory can be read from. This is the emulator when
+/// running as an auto splitter, but can also be a [`FakeMemory`], so all of
+/// the logic can run without any emulator.
+pub trait Memory {
+    fn read<T: Pod>(&self, address: u32) -> Option<T>;
+}
+
+impl Memory for gba::Emulator {
+    fn read<T: Pod>(&self, address: u32) -> Option<T> {
+        gba::Emulator::read(self, address).ok()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) const EWRAM_SIZE: usize = 0x40000;
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) const IWRAM_SIZE: usize = 0x8000;
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) const ROM_HEADER_SIZE: usize = 0xC0;
+
+/// The memory of a GBA that isn't backed by an emulator, but by plain byte
+/// buffers for EWRAM and IWRAM. Only the header of the ROM is available.
+/// EWRAM and IWRAM are on the heap, so moving the memory around doesn't copy
+/// them.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct FakeMemory {
+    pub ewram: Box<[u8]>,
+    pub iwram: Box<[u8]>,
+    pub rom_header: [u8; ROM_HEADER_SIZE],
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FakeMemory {
+    pub fn new() -> Self {
+        Self {
+            ewram: vec![0; EWRAM_SIZE].into_boxed_slice(),
+            iwram: vec![0; IWRAM_SIZE].into_boxed_slice(),
+            rom_header: [0; ROM_HEADER_SIZE],
+        }
+    }
+
+    pub fn write<T: Pod>(&mut self, address: u32, value: T) -> Option<()> {
+        self.write_bytes(address, bytemuck::bytes_of(&value))
+    }
+
+    pub fn write_bytes(&mut self, address: u32, bytes: &[u8]) -> Option<()> {
+        let (buf, offset) = match address >> 24 {
+            0x02 => (&mut self.ewram[..], address & 0x3FFFF),
+            0x03 => (&mut self.iwram[..], address & 0x7FFF),
+            0x08 => (&mut self.rom_header[..], address & 0xFFFFFF),
+            _ => return None,
+        };
+        let offset = offset as usize;
+        buf.get_mut(offset..offset + bytes.len())?
+            .copy_from_slice(bytes);
+        Some(())
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Default for FakeMemory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Memory for FakeMemory {
+    fn read<T: Pod>(&self, address: u32) -> Option<T> {
+        let (buf, offset) = match address >> 24 {
+            0x02 => (&self.ewram[..], address & 0x3FFFF),
+            0x03 => (&self.iwram[..], address & 0x7FFF),
+            0x08 => (&self.rom_header[..], address & 0xFFFFFF),
+            _ => return None,
+        };
+        let offset = offset as usize;
+        let bytes = buf.get(offset..offset + core::mem::size_of::<T>())?;
+        Some(bytemuck::pod_read_unaligned(bytes))
+    }
+}
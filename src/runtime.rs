@@ -0,0 +1,128 @@
+use asr::{itoa, time::Duration, timer::TimerState};
+
+/// Everything the auto splitter does with the timer. This is the actual
+/// auto splitting runtime when running as an auto splitter, but can also be
+/// a [`FakeRuntime`], so all of the logic can run without any timer.
+pub trait Runtime {
+    fn state(&self) -> TimerState;
+    /// The index of the split the timer is on, if it's running.
+    fn current_split_index(&self) -> Option<usize>;
+    fn start(&mut self);
+    fn split(&mut self);
+    fn reset(&mut self);
+    fn pause_game_time(&mut self);
+    fn set_game_time(&mut self, time: Duration);
+    fn set_variable(&mut self, key: &str, value: &str);
+    fn set_variable_int(&mut self, key: &str, value: impl itoa::Integer) {
+        self.set_variable(key, itoa::Buffer::new().format(value));
+    }
+    fn print_message(&mut self, message: &str);
+}
+
+pub struct AsrRuntime;
+
+impl Runtime for AsrRuntime {
+    fn state(&self) -> TimerState {
+        asr::timer::state()
+    }
+
+    fn current_split_index(&self) -> Option<usize> {
+        asr::timer::current_split_index().map(|index| index as usize)
+    }
+
+    fn start(&mut self) {
+        asr::timer::start();
+    }
+
+    fn split(&mut self) {
+        asr::timer::split();
+    }
+
+    fn reset(&mut self) {
+        asr::timer::reset();
+    }
+
+    fn pause_game_time(&mut self) {
+        asr::timer::pause_game_time();
+    }
+
+    fn set_game_time(&mut self, time: Duration) {
+        asr::timer::set_game_time(time);
+    }
+
+    fn set_variable(&mut self, key: &str, value: &str) {
+        asr::timer::set_variable(key, value);
+    }
+
+    fn set_variable_int(&mut self, key: &str, value: impl itoa::Integer) {
+        asr::timer::set_variable_int(key, value);
+    }
+
+    fn print_message(&mut self, message: &str) {
+        asr::print_message(message);
+    }
+}
+
+/// A timer that isn't connected to anything and just keeps track of its
+/// state, the game time and how many times it split. The number of splits is
+/// also the index of the current split, so undoing a split is taking one
+/// away.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct FakeRuntime {
+    pub state: TimerState,
+    pub game_time: Duration,
+    pub splits: usize,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FakeRuntime {
+    pub fn new() -> Self {
+        Self {
+            state: TimerState::NotRunning,
+            game_time: Duration::ZERO,
+            splits: 0,
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Default for FakeRuntime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Runtime for FakeRuntime {
+    fn state(&self) -> TimerState {
+        self.state
+    }
+
+    fn current_split_index(&self) -> Option<usize> {
+        (self.state != TimerState::NotRunning).then_some(self.splits)
+    }
+
+    fn start(&mut self) {
+        self.state = TimerState::Running;
+        self.game_time = Duration::ZERO;
+        self.splits = 0;
+    }
+
+    fn split(&mut self) {
+        self.splits += 1;
+    }
+
+    fn reset(&mut self) {
+        self.state = TimerState::NotRunning;
+    }
+
+    fn pause_game_time(&mut self) {}
+
+    fn set_game_time(&mut self, time: Duration) {
+        self.game_time = time;
+    }
+
+    fn set_variable(&mut self, _: &str, _: &str) {}
+
+    fn print_message(&mut self, _: &str) {}
+}
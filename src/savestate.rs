@@ -0,0 +1,99 @@
+//! Loads the memory of the game out of mGBA save states, so they can be used
+//! as fixtures instead of writing all the memory by hand. Both the plain
+//! save states and the ones embedded into a screenshot PNG are supported.
+
+use alloc::vec::Vec;
+
+use crate::{
+    memory::{FakeMemory, EWRAM_SIZE, IWRAM_SIZE},
+    Region,
+};
+
+const MAGIC: u32 = 0x01000000;
+const TITLE: usize = 0x10;
+const GAME_CODE: usize = 0x1C;
+const IWRAM: usize = 0x19000;
+const EWRAM: usize = 0x21000;
+const SIZE: usize = 0x61000;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+const PNG_STATE_CHUNK: &[u8; 4] = b"gbAs";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The file is neither a save state nor a PNG with one embedded.
+    NotASaveState,
+    /// The file ends before the whole save state is read.
+    Truncated,
+    /// The PNG doesn't have a save state embedded.
+    MissingStateChunk,
+    /// The save state embedded into the PNG couldn't be decompressed.
+    Decompression,
+}
+
+pub struct SaveState {
+    pub memory: FakeMemory,
+    pub game_code: [u8; 4],
+}
+
+impl SaveState {
+    /// Parses an mGBA save state, either a plain one or one embedded into a
+    /// PNG.
+    pub fn parse(data: &[u8]) -> Result<Self, Error> {
+        if data.starts_with(&PNG_SIGNATURE) {
+            let state = decompress(png_state_chunk(data)?)?;
+            Self::parse_raw(&state)
+        } else {
+            Self::parse_raw(data)
+        }
+    }
+
+    fn parse_raw(data: &[u8]) -> Result<Self, Error> {
+        let magic = u32::from_le_bytes(data.get(..4).ok_or(Error::Truncated)?.try_into().unwrap());
+        if magic & 0xFF000000 != MAGIC {
+            return Err(Error::NotASaveState);
+        }
+        let data = data.get(..SIZE).ok_or(Error::Truncated)?;
+
+        let mut memory = FakeMemory::new();
+        memory
+            .iwram
+            .copy_from_slice(&data[IWRAM..IWRAM + IWRAM_SIZE]);
+        memory
+            .ewram
+            .copy_from_slice(&data[EWRAM..EWRAM + EWRAM_SIZE]);
+        // The state keeps a copy of the cartridge header's title and game
+        // code, so put them back where they are in the ROM.
+        memory.rom_header[0xA0..0xB0].copy_from_slice(&data[TITLE..TITLE + 0x10]);
+
+        Ok(Self {
+            memory,
+            game_code: data[GAME_CODE..GAME_CODE + 4].try_into().unwrap(),
+        })
+    }
+
+    /// The release of the game the save state was made with, if it's a
+    /// supported one.
+    pub fn region(&self) -> Option<Region> {
+        Region::from_game_code(&self.game_code)
+    }
+}
+
+fn png_state_chunk(data: &[u8]) -> Result<&[u8], Error> {
+    let mut rest = &data[PNG_SIGNATURE.len()..];
+    while !rest.is_empty() {
+        let header = rest.get(..8).ok_or(Error::Truncated)?;
+        let len = u32::from_be_bytes(header[..4].try_into().unwrap()) as usize;
+        let body = rest.get(8..8 + len).ok_or(Error::Truncated)?;
+        if &header[4..] == PNG_STATE_CHUNK {
+            return Ok(body);
+        }
+        // Skip the chunk and its CRC.
+        rest = rest.get(8 + len + 4..).ok_or(Error::Truncated)?;
+    }
+    Err(Error::MissingStateChunk)
+}
+
+fn decompress(data: &[u8]) -> Result<Vec<u8>, Error> {
+    miniz_oxide::inflate::decompress_to_vec_zlib(data).map_err(|_| Error::Decompression)
+}
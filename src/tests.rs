@@ -0,0 +1,1047 @@
+use super::*;
+use memory::FakeMemory;
+use runtime::FakeRuntime;
+
+/// The values of all the watched variables on a single frame.
+#[derive(Copy, Clone, Zeroable)]
+struct Snapshot {
+    pause_menu: PauseMenu,
+    scene: Scene,
+    task: Task,
+    dhc_big_key: i32,
+    vaati3_phases: i32,
+    sprite: Sprite,
+    frame_count: u16,
+    uix_position: i32,
+    uiy_position: i32,
+    link_position_y: u16,
+    visual_rupees: u16,
+    visual_hearts: u8,
+    visual_keys: u8,
+    tiger_scrolls: u8,
+    mysterious_shells: u16,
+    bombs: u8,
+    playtime: u32,
+}
+
+/// Feeds scripted pairs of snapshots into `should_split`, one frame at a
+/// time.
+struct Splitter {
+    snapshot: Snapshot,
+    accumulated_frame_count: i64,
+    paused_frame_count: Option<i64>,
+    delayed_splits: ArrayVec<(&'static str, i64), MAX_DELAYED_SPLITS>,
+    run_progress: RunProgress,
+    split_history: ArrayVec<RunProgress, MAX_SPLITS>,
+    recording_trace: bool,
+    settings: Settings,
+}
+
+impl Splitter {
+    fn new() -> Self {
+        Self {
+            snapshot: Snapshot {
+                task: Task::GAME,
+                ..Snapshot::zeroed()
+            },
+            accumulated_frame_count: 0,
+            paused_frame_count: None,
+            delayed_splits: ArrayVec::new(),
+            run_progress: Default::default(),
+            split_history: ArrayVec::new(),
+            recording_trace: false,
+            settings: Settings::default(),
+        }
+    }
+
+    fn tick(&mut self, change: impl FnOnce(&mut Snapshot)) -> Option<&'static str> {
+        self.tick_with_frame(change).map(|(name, _)| name)
+    }
+
+    fn tick_with_frame(
+        &mut self,
+        change: impl FnOnce(&mut Snapshot),
+    ) -> Option<(&'static str, i64)> {
+        let old = self.snapshot;
+        self.snapshot.frame_count = self.snapshot.frame_count.wrapping_add(1);
+        change(&mut self.snapshot);
+        let current = self.snapshot;
+
+        fn pair<T>(old: T, current: T) -> Pair<T> {
+            Pair { old, current }
+        }
+
+        let pause_menu = pair(old.pause_menu, current.pause_menu);
+        let scene = pair(old.scene, current.scene);
+        let task = pair(old.task, current.task);
+        let dhc_big_key = pair(old.dhc_big_key, current.dhc_big_key);
+        let vaati3_phases = pair(old.vaati3_phases, current.vaati3_phases);
+        let sprite = pair(old.sprite, current.sprite);
+        let frame_count = pair(old.frame_count, current.frame_count);
+        let uix_position = pair(old.uix_position, current.uix_position);
+        let uiy_position = pair(old.uiy_position, current.uiy_position);
+        let link_position_y = pair(old.link_position_y, current.link_position_y);
+        let visual_rupees = pair(old.visual_rupees, current.visual_rupees);
+        let visual_hearts = pair(old.visual_hearts, current.visual_hearts);
+        let visual_keys = pair(old.visual_keys, current.visual_keys);
+        let tiger_scrolls = pair(old.tiger_scrolls, current.tiger_scrolls);
+        let mysterious_shells = pair(old.mysterious_shells, current.mysterious_shells);
+        let bombs = pair(old.bombs, current.bombs);
+        let playtime = pair(old.playtime, current.playtime);
+
+        let mut vars = Vars {
+            pause_menu: &pause_menu,
+            scene: &scene,
+            task: &task,
+            dhc_big_key: &dhc_big_key,
+            vaati3_phases: &vaati3_phases,
+            sprite: &sprite,
+            frame_count: &frame_count,
+            uix_position: &uix_position,
+            uiy_position: &uiy_position,
+            link_position_y: &link_position_y,
+            visual_rupees: &visual_rupees,
+            visual_hearts: &visual_hearts,
+            visual_keys: &visual_keys,
+            tiger_scrolls: &tiger_scrolls,
+            mysterious_shells: &mysterious_shells,
+            bombs: &bombs,
+            playtime: &playtime,
+            accumulated_frame_count: &mut self.accumulated_frame_count,
+            paused_frame_count: &mut self.paused_frame_count,
+            delayed_splits: &mut self.delayed_splits,
+            run_progress: &mut self.run_progress,
+            split_history: &mut self.split_history,
+            recording_trace: &mut self.recording_trace,
+        };
+        should_split(&mut vars, &self.settings)
+    }
+
+    /// Lets the given number of frames pass without anything changing and
+    /// expects no splits during them.
+    fn idle(&mut self, frames: usize) {
+        for _ in 0..frames {
+            assert_eq!(self.tick(|_| {}), None);
+        }
+    }
+}
+
+/// A game of the Japanese release with all of its memory zeroed, along with a
+/// runtime whose timer is in the given state.
+fn fake_game(timer: TimerState) -> (Game<FakeMemory>, FakeRuntime) {
+    let mut runtime = FakeRuntime::new();
+    runtime.state = timer;
+    (Game::new(FakeMemory::new(), Region::NtscJ), runtime)
+}
+
+const ITEMS: [(usize, InventoryItem, &str); 10] = [
+    (
+        inventory_slot::SMITHS_SWORD,
+        InventoryItem::SMITHS_SWORD,
+        "Get Smith's Sword",
+    ),
+    (
+        inventory_slot::GUST_JAR,
+        InventoryItem::GUST_JAR,
+        "Get Gust Jar",
+    ),
+    (
+        inventory_slot::CANE_OF_PACCI,
+        InventoryItem::CANE_OF_PACCI,
+        "Get Cane of Pacci",
+    ),
+    (
+        inventory_slot::PEGASUS_BOOTS,
+        InventoryItem::PEGASUS_BOOTS,
+        "Get Pegasus Boots",
+    ),
+    (inventory_slot::BOW, InventoryItem::BOW, "Get Bow"),
+    (
+        inventory_slot::MOLE_MITTS,
+        InventoryItem::MOLE_MITTS,
+        "Get Mole Mitts",
+    ),
+    (
+        inventory_slot::OCARINA,
+        InventoryItem::OCARINA,
+        "Get Ocarina",
+    ),
+    (
+        inventory_slot::MAGICAL_BOOMERANG,
+        InventoryItem::MAGICAL_BOOMERANG,
+        "Get Magical Boomerang",
+    ),
+    (
+        inventory_slot::FLAME_LANTERN,
+        InventoryItem::FLAME_LANTERN,
+        "Get Flame Lantern",
+    ),
+    (
+        inventory_slot::ROCS_CAPE,
+        InventoryItem::ROCS_CAPE,
+        "Get Roc's Cape",
+    ),
+];
+
+const ELEMENTS: [(Elements, &str); 4] = [
+    (Elements::EARTH, "Get Earth Element"),
+    (Elements::FIRE, "Get Fire Element"),
+    (Elements::WATER, "Get Water Element"),
+    (Elements::WIND, "Get Wind Element"),
+];
+
+const PERMANENT_EQUIPMENT: [(PermanentEquipment, &str); 3] = [
+    (PermanentEquipment::GRIP_RING, "Get Grip Ring"),
+    (PermanentEquipment::POWER_BRACELETS, "Get Power Bracelets"),
+    (PermanentEquipment::FLIPPERS, "Get Flippers"),
+];
+
+const DUNGEON_ENTRIES: [(Scene, &str); 9] = [
+    (Scene::DEEPWOOD_SHRINE, "Enter Deepwood Shrine"),
+    (
+        Scene::DEEPWOOD_SHRINE_BOSS,
+        "Enter Deepwood Shrine Boss Room",
+    ),
+    (Scene::MT_CRENEL, "Enter Mt. Crenel"),
+    (Scene::CAVE_OF_FLAMES, "Enter Cave of Flames"),
+    (Scene::CAVE_OF_FLAMES_BOSS, "Enter Cave of Flames Boss Room"),
+    (Scene::FORTRESS_OF_WINDS, "Enter Fortress of Winds"),
+    (Scene::TEMPLE_OF_DROPLETS, "Enter Temple of Droplets"),
+    (Scene::PALACE_OF_WINDS, "Enter Palace of Winds"),
+    (Scene::DARK_HYRULE_CASTLE, "Enter DHC"),
+];
+
+#[test]
+fn nothing_happening_does_not_split() {
+    let mut splitter = Splitter::new();
+    splitter.idle(100);
+}
+
+#[test]
+fn items() {
+    for (slot, item, name) in ITEMS {
+        let mut splitter = Splitter::new();
+        assert_eq!(
+            splitter.tick(|s| s.pause_menu.inventory[slot] |= item),
+            Some(name),
+        );
+        splitter.idle(10);
+    }
+}
+
+#[test]
+fn elements() {
+    for (element, name) in ELEMENTS {
+        let mut splitter = Splitter::new();
+        assert_eq!(
+            splitter.tick(|s| s.pause_menu.elements |= element),
+            Some(name),
+        );
+        splitter.idle(10);
+    }
+}
+
+#[test]
+fn permanent_equipment() {
+    for (equipment, name) in PERMANENT_EQUIPMENT {
+        let mut splitter = Splitter::new();
+        assert_eq!(
+            splitter.tick(|s| s.pause_menu.permanent_equipment |= equipment),
+            Some(name),
+        );
+        splitter.idle(10);
+    }
+}
+
+#[test]
+fn disabled_item_does_not_split() {
+    let mut splitter = Splitter::new();
+    splitter.settings.get_gust_jar = false;
+    assert_eq!(
+        splitter.tick(|s| {
+            s.pause_menu.inventory[inventory_slot::GUST_JAR] |= InventoryItem::GUST_JAR
+        }),
+        None,
+    );
+}
+
+#[test]
+fn items_are_only_split_for_once() {
+    let mut splitter = Splitter::new();
+    let sword = |s: &mut Snapshot| {
+        s.pause_menu.inventory[inventory_slot::SMITHS_SWORD] |= InventoryItem::SMITHS_SWORD
+    };
+    assert_eq!(splitter.tick(sword), Some("Get Smith's Sword"));
+    assert_eq!(
+        splitter.tick(|s| s.pause_menu.elements |= Elements::EARTH),
+        Some("Get Earth Element"),
+    );
+    // Going back to the title screen clears the inventory and loading the
+    // save file brings everything back.
+    assert_eq!(splitter.tick(|s| s.pause_menu = PauseMenu::zeroed()), None);
+    assert_eq!(splitter.tick(sword), None);
+    assert_eq!(
+        splitter.tick(|s| s.pause_menu.elements |= Elements::EARTH),
+        None,
+    );
+}
+
+#[test]
+fn loading_a_save_file_does_not_split() {
+    let mut splitter = Splitter::new();
+    splitter.tick(|s| s.task = Task::FILE_SELECT);
+    // The file select screen loads the save file with everything in it.
+    splitter.tick(|s| {
+        s.pause_menu.inventory[inventory_slot::GUST_JAR] |= InventoryItem::GUST_JAR;
+        s.pause_menu.elements |= Elements::EARTH;
+    });
+    assert_eq!(
+        splitter.tick(|s| {
+            s.task = Task::GAME;
+            s.pause_menu.permanent_equipment |= PermanentEquipment::GRIP_RING;
+        }),
+        None,
+    );
+    splitter.idle(10);
+    assert_eq!(
+        splitter.tick(|s| s.pause_menu.elements |= Elements::FIRE),
+        Some("Get Fire Element"),
+    );
+}
+
+#[test]
+fn dungeon_entries_only_split_the_first_time() {
+    for (scene, name) in DUNGEON_ENTRIES {
+        let mut splitter = Splitter::new();
+        // Off by default, so split files from before they were added don't
+        // change.
+        splitter.settings.enter_dhc = true;
+        assert_eq!(splitter.tick(|s| s.scene = scene), Some(name));
+        splitter.idle(10);
+        assert_eq!(splitter.tick(|s| s.scene = Scene::OVERWORLD), None);
+        assert_eq!(splitter.tick(|s| s.scene = scene), None);
+    }
+}
+
+#[test]
+fn disabled_dungeon_entry_does_not_split_later() {
+    let mut splitter = Splitter::new();
+    splitter.settings.enter_deepwood_shrine = false;
+    assert_eq!(splitter.tick(|s| s.scene = Scene::DEEPWOOD_SHRINE), None);
+    assert_eq!(splitter.tick(|s| s.scene = Scene::OVERWORLD), None);
+    splitter.settings.enter_deepwood_shrine = true;
+    assert_eq!(
+        splitter.tick(|s| s.scene = Scene::DEEPWOOD_SHRINE),
+        Some("Enter Deepwood Shrine"),
+    );
+}
+
+#[test]
+fn fortress_of_winds_boss_room_needs_link_at_the_top() {
+    let mut splitter = Splitter::new();
+    assert_eq!(
+        splitter.tick(|s| {
+            s.scene = Scene::FORTRESS_OF_WINDS_GREEN_FLOOR;
+            s.link_position_y = 1200;
+        }),
+        None,
+    );
+    assert_eq!(splitter.tick(|s| s.link_position_y = 1016), None);
+    assert_eq!(
+        splitter.tick(|s| s.link_position_y = 1015),
+        Some("Enter Fortress of Winds Boss Room"),
+    );
+    assert_eq!(splitter.tick(|s| s.link_position_y = 900), None);
+}
+
+#[test]
+fn receive_minish_cap_is_delayed() {
+    let mut splitter = Splitter::new();
+    assert_eq!(
+        splitter.tick(|s| s.sprite = Sprite::RECEIVE_MINISH_CAP),
+        None,
+    );
+    splitter.idle(19);
+    assert_eq!(splitter.tick(|_| {}), Some("Receive Minish Cap"));
+    splitter.idle(10);
+}
+
+#[test]
+fn receive_minish_cap_only_in_minish_woods() {
+    let mut splitter = Splitter::new();
+    splitter.tick(|s| s.scene = Scene::HOUSE);
+    assert_eq!(
+        splitter.tick(|s| s.sprite = Sprite::RECEIVE_MINISH_CAP),
+        None,
+    );
+    splitter.idle(100);
+}
+
+#[test]
+fn get_four_sword_is_delayed() {
+    let mut splitter = Splitter::new();
+    assert_eq!(
+        splitter.tick(|s| {
+            s.pause_menu.inventory[inventory_slot::FOUR_SWORD] |= InventoryItem::FOUR_SWORD
+        }),
+        None,
+    );
+    splitter.idle(243);
+    assert_eq!(splitter.tick(|_| {}), Some("Get Four Sword"));
+    splitter.idle(10);
+}
+
+#[test]
+fn delayed_splits_default_to_their_usual_offsets() {
+    let settings = Settings::default();
+    assert_eq!(settings.receive_minish_cap_offset(), Some(20));
+    assert_eq!(settings.get_four_sword_offset(), Some(244));
+}
+
+#[test]
+fn delayed_splits_happen_at_the_earliest_ticked_offset() {
+    let mut settings = Settings {
+        receive_minish_cap_at_40_frames: true,
+        get_four_sword_at_120_frames: true,
+        ..Default::default()
+    };
+    assert_eq!(settings.receive_minish_cap_offset(), Some(20));
+    assert_eq!(settings.get_four_sword_offset(), Some(120));
+
+    settings.receive_minish_cap = false;
+    assert_eq!(settings.receive_minish_cap_offset(), Some(40));
+    settings.receive_minish_cap_at_40_frames = false;
+    assert_eq!(settings.receive_minish_cap_offset(), None);
+}
+
+#[test]
+fn delayed_splits_with_no_offset_ticked_do_not_split() {
+    let mut splitter = Splitter::new();
+    splitter.settings.receive_minish_cap = false;
+    splitter.tick(|s| s.sprite = Sprite::RECEIVE_MINISH_CAP);
+    splitter.idle(100);
+}
+
+#[test]
+fn delayed_splits_can_split_immediately() {
+    let mut splitter = Splitter::new();
+    splitter.settings.receive_minish_cap_at_0_frames = true;
+    splitter.settings.get_four_sword_at_0_frames = true;
+    assert_eq!(
+        splitter.tick(|s| s.sprite = Sprite::RECEIVE_MINISH_CAP),
+        Some("Receive Minish Cap"),
+    );
+    assert_eq!(
+        splitter.tick(|s| {
+            s.pause_menu.inventory[inventory_slot::FOUR_SWORD] |= InventoryItem::FOUR_SWORD
+        }),
+        Some("Get Four Sword"),
+    );
+    splitter.idle(300);
+}
+
+#[test]
+fn delayed_splits_happen_on_their_frame() {
+    let mut splitter = Splitter::new();
+    splitter.tick(|s| s.sprite = Sprite::RECEIVE_MINISH_CAP);
+    // The ticks come a few frames apart.
+    let late_tick = |s: &mut Snapshot| s.frame_count += 6;
+    assert_eq!(splitter.tick_with_frame(late_tick), None);
+    assert_eq!(splitter.tick_with_frame(late_tick), None);
+    assert_eq!(
+        splitter.tick_with_frame(late_tick),
+        Some(("Receive Minish Cap", 21)),
+    );
+}
+
+#[test]
+fn delayed_split_counts_accumulated_frames() {
+    let mut splitter = Splitter::new();
+    splitter.snapshot.frame_count = 0xFFF0;
+    assert_eq!(
+        splitter.tick(|s| s.sprite = Sprite::RECEIVE_MINISH_CAP),
+        None,
+    );
+    splitter.idle(14);
+    // The frame counter wraps around in the middle of the delay.
+    splitter.accumulated_frame_count += 0x10000;
+    splitter.idle(5);
+    assert_eq!(splitter.tick(|_| {}), Some("Receive Minish Cap"));
+}
+
+#[test]
+fn delayed_splits_wait_for_each_other() {
+    let mut splitter = Splitter::new();
+    let four_sword = |s: &mut Snapshot| {
+        s.pause_menu.inventory[inventory_slot::FOUR_SWORD] |= InventoryItem::FOUR_SWORD
+    };
+    assert_eq!(splitter.tick(four_sword), None);
+    splitter.idle(9);
+    assert_eq!(
+        splitter.tick(|s| s.sprite = Sprite::RECEIVE_MINISH_CAP),
+        None,
+    );
+    splitter.idle(19);
+    // Both are queued up, in the order of their frames.
+    assert_eq!(splitter.tick(|_| {}), Some("Receive Minish Cap"));
+    splitter.idle(213);
+    assert_eq!(splitter.tick(|_| {}), Some("Get Four Sword"));
+    splitter.idle(10);
+}
+
+#[test]
+fn delayed_splits_are_not_queued_up_twice() {
+    let mut splitter = Splitter::new();
+    assert_eq!(
+        splitter.tick(|s| s.sprite = Sprite::RECEIVE_MINISH_CAP),
+        None,
+    );
+    splitter.idle(4);
+    // The sprite flickering doesn't push the split back or split twice.
+    assert_eq!(splitter.tick(|s| s.sprite = Sprite(0)), None);
+    assert_eq!(
+        splitter.tick(|s| s.sprite = Sprite::RECEIVE_MINISH_CAP),
+        None,
+    );
+    splitter.idle(13);
+    assert_eq!(splitter.tick(|_| {}), Some("Receive Minish Cap"));
+    splitter.idle(30);
+}
+
+#[test]
+fn dhc_splits_are_off_by_default() {
+    let mut splitter = Splitter::new();
+    assert_eq!(splitter.tick(|s| s.scene = Scene::DARK_HYRULE_CASTLE), None);
+}
+
+#[test]
+fn dhc_big_key() {
+    let mut splitter = Splitter::new();
+    assert_eq!(splitter.tick(|s| s.dhc_big_key = 1), None);
+    assert_eq!(
+        splitter.tick(|s| s.dhc_big_key |= 4),
+        Some("Get DHC Big Key"),
+    );
+    splitter.idle(10);
+}
+
+#[test]
+fn defeat_vaati() {
+    let mut splitter = Splitter::new();
+    splitter.tick(|s| {
+        s.scene = Scene::VAATI3;
+        s.vaati3_phases = 2;
+    });
+    assert_eq!(splitter.tick(|s| s.vaati3_phases = 1), None);
+    assert_eq!(splitter.tick(|s| s.vaati3_phases = 0), Some("Defeat Vaati"));
+    splitter.idle(10);
+}
+
+#[test]
+fn vaati_phases_outside_the_fight_do_not_split() {
+    let mut splitter = Splitter::new();
+    splitter.tick(|s| {
+        s.scene = Scene::DARK_HYRULE_CASTLE;
+        s.vaati3_phases = 1;
+    });
+    assert_eq!(splitter.tick(|s| s.vaati3_phases = 0), None);
+}
+
+fn write_japanese<T: Pod>(memory: &mut FakeMemory, address: fn(&Addresses) -> u32, value: T) {
+    memory.write(address(&Addresses::JAPAN), value).unwrap();
+}
+
+#[test]
+fn update_starts_the_timer_from_file_select() {
+    let (mut game, mut runtime) = fake_game(TimerState::NotRunning);
+    let settings = Settings::default();
+
+    write_japanese(&mut game.memory, |a| a.task, Task::FILE_SELECT);
+    write_japanese(&mut game.memory, |a| a.uix_position, 24i32);
+    write_japanese(&mut game.memory, |a| a.uiy_position, 144i32);
+    write_japanese(&mut game.memory, |a| a.frame_count, 500u16);
+    game.update(&settings, &mut runtime);
+    assert_eq!(runtime.state, TimerState::NotRunning);
+
+    write_japanese(&mut game.memory, |a| a.uiy_position, 146i32);
+    write_japanese(&mut game.memory, |a| a.frame_count, 501u16);
+    game.update(&settings, &mut runtime);
+    assert_eq!(runtime.state, TimerState::Running);
+
+    write_japanese(&mut game.memory, |a| a.frame_count, 561u16);
+    game.update(&settings, &mut runtime);
+    assert_eq!(runtime.game_time, frame_count::<60>(60));
+}
+
+#[test]
+fn update_accumulates_frame_counter_wraps() {
+    let (mut game, mut runtime) = fake_game(TimerState::Running);
+    let settings = Settings::default();
+
+    write_japanese(&mut game.memory, |a| a.frame_count, 0xFFF0u16);
+    game.update(&settings, &mut runtime);
+    game.accumulated_frame_count = -0xFFF0;
+
+    write_japanese(&mut game.memory, |a| a.frame_count, 0xFFFFu16);
+    game.update(&settings, &mut runtime);
+    assert_eq!(runtime.game_time, frame_count::<60>(0xF));
+
+    write_japanese(&mut game.memory, |a| a.frame_count, 5u16);
+    game.update(&settings, &mut runtime);
+    assert_eq!(runtime.game_time, frame_count::<60>(0x15));
+}
+
+/// Runs the timer through a few seconds of the game and then jumps the frame
+/// counter back by a second, like loading a savestate would.
+fn jump_frame_counter_back(settings: &Settings) -> (Game<FakeMemory>, FakeRuntime) {
+    let (mut game, mut runtime) = fake_game(TimerState::Running);
+
+    for frames in [1000u16, 1300, 1600] {
+        write_japanese(&mut game.memory, |a| a.frame_count, frames);
+        game.update(settings, &mut runtime);
+    }
+    assert_eq!(runtime.game_time, frame_count::<60>(1600));
+
+    write_japanese(&mut game.memory, |a| a.frame_count, 1540u16);
+    game.update(settings, &mut runtime);
+    (game, runtime)
+}
+
+fn advance_frame_counter(
+    (game, runtime): &mut (Game<FakeMemory>, FakeRuntime),
+    settings: &Settings,
+    to: u16,
+) {
+    write_japanese(&mut game.memory, |a| a.frame_count, to);
+    game.update(settings, runtime);
+}
+
+#[test]
+fn update_ignores_frame_counter_jumps() {
+    let settings = Settings::default();
+    let mut run = jump_frame_counter_back(&settings);
+    assert_eq!(run.1.game_time, frame_count::<60>(1600));
+
+    advance_frame_counter(&mut run, &settings, 1550);
+    assert_eq!(run.1.game_time, frame_count::<60>(1610));
+
+    // Jumping ahead is ignored as well.
+    advance_frame_counter(&mut run, &settings, 40000);
+    assert_eq!(run.1.game_time, frame_count::<60>(1610));
+}
+
+#[test]
+fn update_subtracts_frame_counter_jumps() {
+    let settings = Settings {
+        subtract_frame_counter_jumps: true,
+        ..Default::default()
+    };
+    let mut run = jump_frame_counter_back(&settings);
+    assert_eq!(run.1.game_time, frame_count::<60>(1540));
+
+    advance_frame_counter(&mut run, &settings, 1550);
+    assert_eq!(run.1.game_time, frame_count::<60>(1550));
+}
+
+#[test]
+fn update_pauses_on_frame_counter_jumps() {
+    let settings = Settings {
+        pause_on_frame_counter_jumps: true,
+        ..Default::default()
+    };
+    let mut run = jump_frame_counter_back(&settings);
+    assert_eq!(run.1.game_time, frame_count::<60>(1600));
+
+    advance_frame_counter(&mut run, &settings, 1590);
+    assert_eq!(run.1.game_time, frame_count::<60>(1600));
+    advance_frame_counter(&mut run, &settings, 1610);
+    assert_eq!(run.1.game_time, frame_count::<60>(1610));
+}
+
+#[test]
+fn subtracting_frame_counter_jumps_wins_over_pausing() {
+    let settings = Settings {
+        subtract_frame_counter_jumps: true,
+        pause_on_frame_counter_jumps: true,
+        ..Default::default()
+    };
+    let mut run = jump_frame_counter_back(&settings);
+    assert_eq!(run.1.game_time, frame_count::<60>(1540));
+
+    advance_frame_counter(&mut run, &settings, 1550);
+    assert_eq!(run.1.game_time, frame_count::<60>(1550));
+}
+
+#[test]
+fn update_resets_on_soft_reset() {
+    let (mut game, mut runtime) = fake_game(TimerState::Running);
+    let mut settings = Settings::default();
+
+    write_japanese(&mut game.memory, |a| a.task, Task::GAME);
+    game.update(&settings, &mut runtime);
+    game.run_progress.collected.elements |= Elements::EARTH;
+    game.delayed_splits.push(("Get Four Sword", 2244));
+
+    // Game over doesn't reset.
+    settings.reset_on_soft_reset = true;
+    write_japanese(&mut game.memory, |a| a.task, Task::GAME_OVER);
+    game.update(&settings, &mut runtime);
+    assert_eq!(runtime.state, TimerState::Running);
+
+    write_japanese(&mut game.memory, |a| a.task, Task::TRANSITION);
+    game.update(&settings, &mut runtime);
+    write_japanese(&mut game.memory, |a| a.task, Task::TITLE);
+    game.update(&settings, &mut runtime);
+    assert_eq!(runtime.state, TimerState::NotRunning);
+    assert!(game.run_progress.collected.elements.is_empty());
+    assert!(game.delayed_splits.is_empty());
+}
+
+#[test]
+fn update_keeps_running_through_soft_resets_by_default() {
+    let (mut game, mut runtime) = fake_game(TimerState::Running);
+    let settings = Settings::default();
+
+    write_japanese(&mut game.memory, |a| a.task, Task::GAME);
+    game.update(&settings, &mut runtime);
+    write_japanese(&mut game.memory, |a| a.task, Task::TITLE);
+    game.update(&settings, &mut runtime);
+    assert_eq!(runtime.state, TimerState::Running);
+}
+
+#[test]
+fn receive_minish_cap_needs_the_game_to_run() {
+    let mut splitter = Splitter::new();
+    splitter.snapshot.task = Task::TITLE;
+    assert_eq!(
+        splitter.tick(|s| s.sprite = Sprite::RECEIVE_MINISH_CAP),
+        None,
+    );
+    splitter.idle(30);
+}
+
+#[test]
+fn undoing_a_split_splits_for_it_again() {
+    let (mut game, mut runtime) = fake_game(TimerState::Running);
+    let settings = Settings::default();
+
+    game.update(&settings, &mut runtime);
+    write_japanese(&mut game.memory, |a| a.scene, Scene::DEEPWOOD_SHRINE);
+    assert_eq!(
+        game.update(&settings, &mut runtime),
+        Some(("Enter Deepwood Shrine", 0)),
+    );
+    write_japanese(&mut game.memory, |a| a.scene, Scene::OVERWORLD);
+    game.update(&settings, &mut runtime);
+
+    runtime.splits -= 1;
+    assert_eq!(game.update(&settings, &mut runtime), None);
+    write_japanese(&mut game.memory, |a| a.scene, Scene::DEEPWOOD_SHRINE);
+    assert_eq!(
+        game.update(&settings, &mut runtime),
+        Some(("Enter Deepwood Shrine", 0)),
+    );
+}
+
+#[test]
+fn undoing_a_split_inside_the_dungeon_waits_for_it_to_be_entered_again() {
+    let (mut game, mut runtime) = fake_game(TimerState::Running);
+    let settings = Settings::default();
+
+    game.update(&settings, &mut runtime);
+    write_japanese(&mut game.memory, |a| a.scene, Scene::DEEPWOOD_SHRINE);
+    assert_eq!(
+        game.update(&settings, &mut runtime),
+        Some(("Enter Deepwood Shrine", 0)),
+    );
+
+    runtime.splits -= 1;
+    assert_eq!(game.update(&settings, &mut runtime), None);
+    assert_eq!(game.update(&settings, &mut runtime), None);
+    write_japanese(&mut game.memory, |a| a.scene, Scene::OVERWORLD);
+    assert_eq!(game.update(&settings, &mut runtime), None);
+    write_japanese(&mut game.memory, |a| a.scene, Scene::DEEPWOOD_SHRINE);
+    assert_eq!(
+        game.update(&settings, &mut runtime),
+        Some(("Enter Deepwood Shrine", 0)),
+    );
+}
+
+#[test]
+fn skipping_a_split_keeps_the_run_progress() {
+    let (mut game, mut runtime) = fake_game(TimerState::Running);
+    let settings = Settings::default();
+
+    game.update(&settings, &mut runtime);
+    write_japanese(&mut game.memory, |a| a.scene, Scene::DEEPWOOD_SHRINE);
+    game.update(&settings, &mut runtime);
+    runtime.splits += 1;
+    write_japanese(&mut game.memory, |a| a.scene, Scene::OVERWORLD);
+    game.update(&settings, &mut runtime);
+
+    // Undoing the skipped split doesn't undo entering Deepwood Shrine.
+    runtime.splits -= 1;
+    game.update(&settings, &mut runtime);
+    write_japanese(&mut game.memory, |a| a.scene, Scene::DEEPWOOD_SHRINE);
+    assert_eq!(game.update(&settings, &mut runtime), None);
+}
+
+#[test]
+fn update_sets_the_game_time_to_the_frame_of_the_split() {
+    let (mut game, mut runtime) = fake_game(TimerState::Running);
+    let settings = Settings::default();
+
+    write_japanese(&mut game.memory, |a| a.task, Task::GAME);
+    game.update(&settings, &mut runtime);
+    let mut pause_menu = PauseMenu::zeroed();
+    pause_menu.inventory[inventory_slot::FOUR_SWORD] |= InventoryItem::FOUR_SWORD;
+    write_japanese(&mut game.memory, |a| a.pause_menu, pause_menu);
+    write_japanese(&mut game.memory, |a| a.frame_count, 100u16);
+    game.update(&settings, &mut runtime);
+
+    write_japanese(&mut game.memory, |a| a.frame_count, 350u16);
+    assert_eq!(
+        game.update(&settings, &mut runtime),
+        Some(("Get Four Sword", 344)),
+    );
+    assert_eq!(runtime.game_time, frame_count::<60>(344));
+}
+
+#[test]
+fn update_takes_the_game_time_from_the_playtime() {
+    let (mut game, mut runtime) = fake_game(TimerState::Running);
+    let settings = Settings {
+        game_time_from_playtime: true,
+        ..Default::default()
+    };
+
+    write_japanese(&mut game.memory, |a| a.frame_count, 100u16);
+    write_japanese(&mut game.memory, |a| a.playtime, 5000u32);
+    game.update(&settings, &mut runtime);
+    assert_eq!(runtime.game_time, frame_count::<60>(5000));
+}
+
+#[test]
+fn update_can_leave_the_game_time_alone() {
+    let (mut game, mut runtime) = fake_game(TimerState::Running);
+    let settings = Settings {
+        no_game_time: true,
+        ..Default::default()
+    };
+
+    write_japanese(&mut game.memory, |a| a.frame_count, 100u16);
+    game.update(&settings, &mut runtime);
+    assert_eq!(runtime.game_time, Duration::ZERO);
+}
+
+#[test]
+fn no_game_time_wins_over_the_playtime() {
+    let (mut game, mut runtime) = fake_game(TimerState::Running);
+    let settings = Settings {
+        game_time_from_playtime: true,
+        no_game_time: true,
+        ..Default::default()
+    };
+
+    write_japanese(&mut game.memory, |a| a.frame_count, 100u16);
+    write_japanese(&mut game.memory, |a| a.playtime, 5000u32);
+    game.update(&settings, &mut runtime);
+    assert_eq!(runtime.game_time, Duration::ZERO);
+}
+
+#[test]
+fn update_times_by_the_gba_frame_rate() {
+    let (mut game, mut runtime) = fake_game(TimerState::Running);
+    let settings = Settings {
+        gba_frame_rate: true,
+        ..Default::default()
+    };
+
+    game.memory
+        .write(Addresses::JAPAN.frame_count, 5973u16)
+        .unwrap();
+    game.update(&settings, &mut runtime);
+    assert_eq!(
+        runtime.game_time,
+        Duration::seconds_f64(5973.0 / GBA_FRAME_RATE),
+    );
+}
+
+#[test]
+fn update_splits_through_the_runtime() {
+    let (mut game, mut runtime) = fake_game(TimerState::Running);
+    let settings = Settings::default();
+
+    write_japanese(&mut game.memory, |a| a.task, Task::GAME);
+    game.update(&settings, &mut runtime);
+    let mut pause_menu = PauseMenu::zeroed();
+    pause_menu.inventory[inventory_slot::GUST_JAR] |= InventoryItem::GUST_JAR;
+    game.memory
+        .write(Addresses::JAPAN.pause_menu, pause_menu)
+        .unwrap();
+    assert_eq!(
+        game.update(&settings, &mut runtime),
+        Some(("Get Gust Jar", 0)),
+    );
+    assert_eq!(runtime.splits, 1);
+}
+
+#[test]
+fn playtime_is_formatted_like_a_timer() {
+    assert_eq!(&format_time(frame_count::<60>(0)), "0:00:00.00");
+    assert_eq!(
+        &format_time(frame_count::<60>(((3600 + 2 * 60 + 3) * 60) + 27)),
+        "1:02:03.45",
+    );
+}
+
+#[test]
+fn trace_lines_are_found_anywhere_in_the_log() {
+    assert!(matches!(
+        trace::Line::parse("12:00:00 [trace] start BZMJ"),
+        Some(trace::Line::Start(Region::NtscJ, _)),
+    ));
+    assert!(trace::Line::parse("Get Gust Jar").is_none());
+    assert!(trace::Line::parse("[trace] start XXXX").is_none());
+    // The USA release isn't supported yet.
+    assert!(trace::Line::parse("[trace] start BZME").is_none());
+}
+
+#[test]
+fn trace_ticks_list_the_changed_bytes() {
+    let Some(trace::Line::Tick(mut changes)) =
+        trace::Line::parse("[trace] 300100C=d204 3000BF4=48")
+    else {
+        panic!("not a tick");
+    };
+    let (address, value) = changes.next().unwrap().unwrap();
+    assert_eq!(address, 0x300100C);
+    assert_eq!(&value[..], &[0xD2, 0x04]);
+    let (address, value) = changes.next().unwrap().unwrap();
+    assert_eq!(address, 0x3000BF4);
+    assert_eq!(&value[..], &[0x48]);
+    assert!(changes.next().is_none());
+}
+
+/// Adds the variable to the tick of a trace.
+fn write_variable(line: &mut ArrayString<1024>, address: u32, value: &[u8]) {
+    write!(line, " {address:X}=").unwrap();
+    for byte in value {
+        write!(line, "{byte:02x}").unwrap();
+    }
+}
+
+#[test]
+fn traces_have_to_list_every_watched_variable() {
+    let mut game = Game::new(trace::ZeroedMemory, Region::NtscJ);
+    let mut first_tick = ArrayString::from("[trace]").unwrap();
+    // A trace from before the task was watched.
+    let mut outdated = first_tick;
+    let vars = game.update_vars().unwrap();
+    vars.for_each_watched(&Addresses::JAPAN, |address, value, _| {
+        write_variable(&mut first_tick, address, value);
+        if address != Addresses::JAPAN.task {
+            write_variable(&mut outdated, address, value);
+        }
+    });
+
+    let mut replay = trace::Replay::new(false);
+    replay.feed("[trace] start BZMJ").unwrap();
+    assert!(replay.feed(&first_tick).is_ok());
+
+    replay.feed("[trace] start BZMJ").unwrap();
+    assert!(matches!(
+        replay.feed(&outdated),
+        Err(trace::ReplayError::Missing(0x3001002)),
+    ));
+}
+
+#[test]
+fn traces_start_with_the_settings_that_were_changed() {
+    assert_eq!(
+        &trace::start_line(Region::NtscJ, &Settings::default()),
+        "[trace] start BZMJ",
+    );
+    let settings = Settings {
+        get_gust_jar: false,
+        enter_dhc: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        &trace::start_line(Region::NtscJ, &settings),
+        "[trace] start BZMJ -get_gust_jar +enter_dhc",
+    );
+}
+
+#[test]
+fn traces_are_replayed_with_their_settings() {
+    let mut game = Game::new(trace::ZeroedMemory, Region::NtscJ);
+    let mut first_tick = ArrayString::from("[trace]").unwrap();
+    let vars = game.update_vars().unwrap();
+    vars.for_each_watched(&Addresses::JAPAN, |address, value, _| {
+        write_variable(&mut first_tick, address, value);
+    });
+    let mut in_game = ArrayString::from("[trace]").unwrap();
+    write_variable(
+        &mut in_game,
+        Addresses::JAPAN.task,
+        bytemuck::bytes_of(&Task::GAME),
+    );
+    let mut pause_menu = PauseMenu::zeroed();
+    pause_menu.inventory[inventory_slot::GUST_JAR] |= InventoryItem::GUST_JAR;
+    let mut gust_jar = ArrayString::from("[trace]").unwrap();
+    write_variable(
+        &mut gust_jar,
+        Addresses::JAPAN.pause_menu,
+        bytemuck::bytes_of(&pause_menu),
+    );
+
+    let replay = |start| {
+        let mut replay = trace::Replay::new(true);
+        replay.feed(start).unwrap();
+        replay.feed(&first_tick).unwrap();
+        replay.feed(&in_game).unwrap();
+        replay.feed(&gust_jar).unwrap()
+    };
+    assert_eq!(replay("[trace] start BZMJ"), Some(("Get Gust Jar", 0)));
+    assert_eq!(replay("[trace] start BZMJ -get_gust_jar"), None);
+}
+
+#[test]
+fn traces_with_unknown_settings_are_not_replayed() {
+    let mut replay = trace::Replay::new(false);
+    assert!(matches!(
+        replay.feed("[trace] start BZMJ +no_such_setting"),
+        Err(trace::ReplayError::UnknownSetting),
+    ));
+    assert!(matches!(
+        replay.feed("[trace] start BZMJ enter_dhc"),
+        Err(trace::ReplayError::Malformed),
+    ));
+    assert!(!replay.has_started());
+}
+
+/// Memory that can only be read once it's made readable.
+struct UnreadableMemory(bool);
+
+impl Memory for UnreadableMemory {
+    fn read<T: Pod>(&self, _: u32) -> Option<T> {
+        self.0.then(T::zeroed)
+    }
+}
+
+#[test]
+fn traces_start_on_the_first_tick_that_is_read() {
+    let settings = Settings {
+        record_memory_trace: true,
+        ..Default::default()
+    };
+    let mut game = Game::new(UnreadableMemory(false), Region::NtscJ);
+    let mut runtime = FakeRuntime::new();
+    assert_eq!(game.update(&settings, &mut runtime), None);
+    assert!(!game.recording_trace);
+
+    game.memory.0 = true;
+    game.update(&settings, &mut runtime);
+    assert!(game.recording_trace);
+}
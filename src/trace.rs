@@ -0,0 +1,279 @@
+//! Memory traces are a compact record of all the watched variables that are
+//! written to the auto splitter's log during a run, so the run can be
+//! replayed later on without the game.
+//!
+//! A trace starts with a line naming the release of the game and the settings
+//! that were ticked (`+`) or unticked (`-`) from their defaults, followed by a
+//! line for every tick where any of the watched variables changed. The first
+//! tick lists all of them, every tick after that only the ones that changed,
+//! as their address and their bytes in memory order:
+//!
+//! ```text
+//! [trace] start BZMJ +enter_dhc -get_gust_jar
+//! [trace] 2002B32=000000000000000000000000000000000000 3000BF4=00 ...
+//! [trace] 300100C=d204
+//! [trace] 300100C=d304 3000BF4=48
+//! ```
+//!
+//! Anything in front of the `[trace]` marker and lines without it are
+//! ignored, so the whole log can be replayed as is.
+
+use arrayvec::{ArrayString, ArrayVec};
+use core::fmt::Write;
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{
+    memory::{FakeMemory, Memory},
+    runtime::FakeRuntime,
+    Game,
+};
+use crate::{runtime::Runtime, Region, Settings, Vars};
+#[cfg(not(target_arch = "wasm32"))]
+use asr::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use bytemuck::Pod;
+
+const MARKER: &str = "[trace]";
+
+/// The largest watched variable that fits into a trace.
+pub const MAX_VALUE_SIZE: usize = 32;
+
+/// Writes the tick to the log, if any of the watched variables changed. If
+/// `start` is set, a trace is started and all of them are written.
+pub(crate) fn record(
+    vars: &Vars<'_>,
+    region: Region,
+    settings: &Settings,
+    start: bool,
+    runtime: &mut impl Runtime,
+) {
+    if start {
+        runtime.print_message(&start_line(region, settings));
+    }
+
+    let mut line = ArrayString::<512>::new();
+    line.push_str(MARKER);
+    vars.for_each_watched(region.addresses(), |address, value, changed| {
+        if start || changed {
+            let _ = write!(line, " {address:X}=");
+            for byte in value {
+                let _ = write!(line, "{byte:02x}");
+            }
+        }
+    });
+    if line.len() > MARKER.len() {
+        runtime.print_message(&line);
+    }
+}
+
+/// The line that starts a trace for the release of the game, recorded with
+/// the settings.
+pub(crate) fn start_line(region: Region, settings: &Settings) -> ArrayString<2048> {
+    let mut line = ArrayString::new();
+    line.push_str(MARKER);
+    line.push_str(" start ");
+    line.push_str(region.game_code());
+    settings.for_each_changed(|key, value| {
+        let _ = write!(line, " {}{key}", if value { '+' } else { '-' });
+    });
+    line
+}
+
+/// A single line of a trace.
+pub enum Line<'a> {
+    /// A new trace starts for the given release of the game, with the
+    /// settings that aren't at their defaults.
+    Start(Region, SettingChanges<'a>),
+    /// A tick along with the variables that changed during it.
+    Tick(Changes<'a>),
+}
+
+impl<'a> Line<'a> {
+    /// Parses a line of the log. Returns [`None`] if the line isn't part of a
+    /// trace.
+    pub fn parse(line: &'a str) -> Option<Self> {
+        let (_, rest) = line.split_once(MARKER)?;
+        let rest = rest.trim();
+        Some(match rest.strip_prefix("start ") {
+            Some(start) => {
+                let mut start = start.split_ascii_whitespace();
+                let game_code = start.next()?.as_bytes().try_into().ok()?;
+                Line::Start(Region::from_game_code(game_code)?, SettingChanges(start))
+            }
+            None => Line::Tick(Changes(rest.split_ascii_whitespace())),
+        })
+    }
+}
+
+/// The settings a trace was recorded with that aren't at their defaults, as
+/// their keys and their values. Yields [`None`] for any setting that is
+/// malformed.
+pub struct SettingChanges<'a>(core::str::SplitAsciiWhitespace<'a>);
+
+impl<'a> Iterator for SettingChanges<'a> {
+    type Item = Option<(&'a str, bool)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let change = self.0.next()?;
+        Some(if let Some(key) = change.strip_prefix('+') {
+            Some((key, true))
+        } else {
+            change.strip_prefix('-').map(|key| (key, false))
+        })
+    }
+}
+
+/// The variables that changed during a tick. Yields [`None`] for any change
+/// that is malformed.
+pub struct Changes<'a>(core::str::SplitAsciiWhitespace<'a>);
+
+impl Iterator for Changes<'_> {
+    type Item = Option<(u32, ArrayVec<u8, MAX_VALUE_SIZE>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let change = self.0.next()?;
+        Some(parse_change(change))
+    }
+}
+
+fn parse_change(change: &str) -> Option<(u32, ArrayVec<u8, MAX_VALUE_SIZE>)> {
+    let (address, value) = change.split_once('=')?;
+    let address = u32::from_str_radix(address, 16).ok()?;
+    if value.len() % 2 != 0 {
+        return None;
+    }
+    let mut bytes = ArrayVec::new();
+    for i in (0..value.len()).step_by(2) {
+        let byte = u8::from_str_radix(value.get(i..i + 2)?, 16).ok()?;
+        bytes.try_push(byte).ok()?;
+    }
+    Some((address, bytes))
+}
+
+/// Why a trace can't be replayed.
+#[derive(Debug)]
+pub enum ReplayError {
+    /// A line of the trace couldn't be parsed.
+    Malformed,
+    /// The trace was recorded with a setting that doesn't exist anymore.
+    UnknownSetting,
+    /// The first tick of the trace doesn't list the watched variable at the
+    /// address, because the trace was recorded before it was watched. Such a
+    /// trace needs to be recorded again rather than edited, so it still shows
+    /// what the game actually did.
+    Missing(u32),
+}
+
+/// Replays the traces in a log without the game, running the auto splitter
+/// the same way it ran when they were recorded.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct Replay {
+    game: Option<Game<FakeMemory>>,
+    // The settings the trace was recorded with.
+    settings: Settings,
+    runtime: FakeRuntime,
+    running: bool,
+    first_tick: bool,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Replay {
+    /// Creates a new replay. If `running` is set, the timer is already
+    /// running when a trace starts, for traces that were started in the
+    /// middle of a run.
+    pub fn new(running: bool) -> Self {
+        Self {
+            game: None,
+            settings: Settings::default(),
+            runtime: FakeRuntime::new(),
+            running,
+            first_tick: false,
+        }
+    }
+
+    /// Whether the log contained the start of a trace so far.
+    pub fn has_started(&self) -> bool {
+        self.game.is_some()
+    }
+
+    pub fn game_time(&self) -> Duration {
+        self.runtime.game_time
+    }
+
+    /// Feeds the next line of the log to the replay and returns the split
+    /// that happened on it, if any, along with the frame it happened on.
+    /// Lines that aren't part of a trace are skipped.
+    pub fn feed(&mut self, line: &str) -> Result<Option<(&'static str, i64)>, ReplayError> {
+        match Line::parse(line) {
+            Some(Line::Start(region, changes)) => {
+                self.game = None;
+                self.settings = Settings::default();
+                for change in changes {
+                    let (key, value) = change.ok_or(ReplayError::Malformed)?;
+                    self.settings
+                        .set(key, value)
+                        .ok_or(ReplayError::UnknownSetting)?;
+                }
+                // The replay doesn't record another trace of itself.
+                self.settings.record_memory_trace = false;
+                self.game = Some(Game::new(FakeMemory::new(), region));
+                self.runtime = FakeRuntime::new();
+                self.first_tick = true;
+                if self.running {
+                    self.runtime.start();
+                }
+                Ok(None)
+            }
+            Some(Line::Tick(changes)) => {
+                let Some(game) = &mut self.game else {
+                    return Ok(None);
+                };
+                if self.first_tick {
+                    self.first_tick = false;
+                    if let Some(address) = missing_variable(game.region, line) {
+                        return Err(ReplayError::Missing(address));
+                    }
+                }
+                for change in changes {
+                    let (address, value) = change.ok_or(ReplayError::Malformed)?;
+                    game.memory_mut()
+                        .write_bytes(address, &value)
+                        .ok_or(ReplayError::Malformed)?;
+                }
+                Ok(game.update(&self.settings, &mut self.runtime))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// Memory that is all zeros, just to find out which variables are watched.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) struct ZeroedMemory;
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Memory for ZeroedMemory {
+    fn read<T: Pod>(&self, _: u32) -> Option<T> {
+        Some(T::zeroed())
+    }
+}
+
+/// Finds a watched variable that the tick doesn't list.
+#[cfg(not(target_arch = "wasm32"))]
+fn missing_variable(region: Region, tick: &str) -> Option<u32> {
+    let listed = |address| match Line::parse(tick) {
+        Some(Line::Tick(mut changes)) => {
+            changes.any(|change| change.is_some_and(|(listed, _)| listed == address))
+        }
+        _ => false,
+    };
+    let mut game = Game::new(ZeroedMemory, region);
+    let vars = game.update_vars()?;
+    let mut missing = None;
+    vars.for_each_watched(region.addresses(), |address, _, _| {
+        if missing.is_none() && !listed(address) {
+            missing = Some(address);
+        }
+    });
+    missing
+}
@@ -0,0 +1,103 @@
+#![cfg(feature = "savestates")]
+
+use minish_cap_auto_splitter::{
+    runtime::{FakeRuntime, Runtime},
+    savestate::{Error, SaveState},
+    Game, Region, Settings,
+};
+
+const SIZE: usize = 0x61000;
+const IWRAM: usize = 0x19000;
+const EWRAM: usize = 0x21000;
+
+const PAUSE_MENU: usize = 0x2B32;
+const ELEMENTS: usize = PAUSE_MENU + 16;
+const SCENE: usize = 0xBF4;
+const TASK: usize = 0x1002;
+const GAME: u8 = 3;
+const CAVE_OF_FLAMES_BOSS: u8 = 0x51;
+const EARTH: u8 = 1 << 0;
+const FIRE: u8 = 1 << 2;
+
+/// A save state made in Gleerok's room, right before the Fire Element is
+/// picked up.
+fn before_fire_element() -> Vec<u8> {
+    let mut state = vec![0; SIZE];
+    state[..4].copy_from_slice(&0x01000008u32.to_le_bytes());
+    state[0x10..0x1C].copy_from_slice(b"GBAZELDA MC\0");
+    state[0x1C..0x20].copy_from_slice(b"BZMJ");
+    state[IWRAM + SCENE] = CAVE_OF_FLAMES_BOSS;
+    state[IWRAM + TASK] = GAME;
+    state[EWRAM + ELEMENTS] = EARTH;
+    state
+}
+
+fn png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    // mGBA doesn't check the CRC, so neither do we.
+    png.extend_from_slice(&[0; 4]);
+}
+
+fn embed_into_png(state: &[u8]) -> Vec<u8> {
+    let mut png = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+    png_chunk(&mut png, b"IHDR", &[0; 13]);
+    png_chunk(&mut png, b"IDAT", &[]);
+    png_chunk(
+        &mut png,
+        b"gbAs",
+        &miniz_oxide::deflate::compress_to_vec_zlib(state, 6),
+    );
+    png_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+#[test]
+fn plain_save_state() {
+    let state = SaveState::parse(&before_fire_element()).unwrap();
+    assert!(state.region() == Some(Region::NtscJ));
+    assert_eq!(state.memory.iwram[SCENE], CAVE_OF_FLAMES_BOSS);
+    assert_eq!(state.memory.ewram[ELEMENTS], EARTH);
+    assert_eq!(&state.memory.rom_header[0xAC..0xB0], b"BZMJ");
+}
+
+#[test]
+fn save_state_embedded_into_png() {
+    let state = SaveState::parse(&embed_into_png(&before_fire_element())).unwrap();
+    assert!(state.region() == Some(Region::NtscJ));
+    assert_eq!(state.memory.ewram[ELEMENTS], EARTH);
+}
+
+#[test]
+fn invalid_save_states() {
+    assert_eq!(SaveState::parse(&[0; 16]).err(), Some(Error::NotASaveState),);
+    assert_eq!(
+        SaveState::parse(&before_fire_element()[..0x30000]).err(),
+        Some(Error::Truncated),
+    );
+    let mut png = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+    png_chunk(&mut png, b"IEND", &[]);
+    assert_eq!(SaveState::parse(&png).err(), Some(Error::MissingStateChunk),);
+}
+
+#[test]
+fn fire_element_splits_on_the_next_frame() {
+    let state = SaveState::parse(&embed_into_png(&before_fire_element())).unwrap();
+    let region = state.region().unwrap();
+    let mut game = Game::new(state.memory, region);
+    let settings = Settings::default();
+    let mut runtime = FakeRuntime::new();
+    runtime.start();
+
+    // The state is already inside of the boss room, so it isn't entered.
+    assert_eq!(game.update(&settings, &mut runtime), None);
+    assert_eq!(game.update(&settings, &mut runtime), None);
+
+    game.memory_mut().ewram[ELEMENTS] |= FIRE;
+    game.memory_mut().iwram[0x100C] = 1;
+    assert_eq!(
+        game.update(&settings, &mut runtime),
+        Some(("Get Fire Element", 1)),
+    );
+}
@@ -0,0 +1,422 @@
+//! Runs the compiled auto splitter in a mock auto splitting runtime, to catch
+//! problems that only show up in the actual wasm module, such as panics
+//! turning into traps or settings that don't register.
+//!
+//! The module needs to be built first:
+//!
+//! ```text
+//! cargo build --release
+//! cargo test-host
+//! ```
+//!
+//! `AUTO_SPLITTER_WASM` can point to a different module. If there is no
+//! module, the tests fail, unless `SKIP_WASM_TESTS` is set to skip them.
+
+use std::{collections::HashMap, env, fs, path::PathBuf};
+
+use minish_cap_auto_splitter::Settings;
+use wasmi::{
+    core::{Trap, ValueType},
+    Caller, Engine, Extern, Func, Linker, Module, Store, TypedFunc, Value,
+};
+
+const EWRAM_BASE: u64 = 0x1000_0000;
+const IWRAM_BASE: u64 = 0x2000_0000;
+const ROM_BASE: u64 = 0x3000_0000;
+
+const NOT_RUNNING: i32 = 0;
+const RUNNING: i32 = 1;
+
+/// An emulator process with the memory of the emulated GBA mapped into it.
+/// EWRAM and IWRAM each are their own memory range. Like on the actual
+/// runtime, the ROM isn't readable, unless its header is mapped as a third
+/// range.
+struct FakeEmulator {
+    ewram: Vec<u8>,
+    iwram: Vec<u8>,
+    rom: Option<Vec<u8>>,
+}
+
+impl FakeEmulator {
+    fn new() -> Self {
+        Self {
+            ewram: vec![0; 0x40000],
+            iwram: vec![0; 0x8000],
+            rom: None,
+        }
+    }
+
+    fn with_rom_header(game_code: &[u8; 4]) -> Self {
+        let mut emulator = Self {
+            rom: Some(vec![0; 0xC0]),
+            ..Self::new()
+        };
+        emulator.write(0x80000A0, b"GBAZELDA MC\0");
+        emulator.write(0x80000AC, game_code);
+        emulator
+    }
+
+    fn ranges(&self) -> Vec<(u64, &[u8])> {
+        let mut ranges = vec![(EWRAM_BASE, &self.ewram[..]), (IWRAM_BASE, &self.iwram[..])];
+        if let Some(rom) = &self.rom {
+            ranges.push((ROM_BASE, &rom[..]));
+        }
+        ranges
+    }
+
+    fn read(&self, address: u64, buf: &mut [u8]) -> bool {
+        for (base, range) in self.ranges() {
+            if let Some(offset) = address.checked_sub(base) {
+                let offset = offset as usize;
+                if let Some(bytes) = range.get(offset..offset + buf.len()) {
+                    buf.copy_from_slice(bytes);
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// Writes to the memory of the GBA.
+    fn write(&mut self, address: u32, bytes: &[u8]) {
+        let (range, offset) = match address >> 24 {
+            0x02 => (&mut self.ewram, address & 0x3FFFF),
+            0x03 => (&mut self.iwram, address & 0x7FFF),
+            0x08 => (self.rom.as_mut().unwrap(), address & 0xFFFFFF),
+            _ => panic!("{address:X} isn't mapped"),
+        };
+        let offset = offset as usize;
+        range[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+}
+
+#[derive(Default)]
+struct Host {
+    emulator: Option<FakeEmulator>,
+    attached_to: Vec<String>,
+    settings: Vec<(String, bool)>,
+    variables: HashMap<String, String>,
+    messages: Vec<String>,
+    timer_state: i32,
+    splits: Vec<String>,
+    game_time: Option<(i64, i32)>,
+}
+
+fn int(params: &[Value], index: usize) -> u64 {
+    match params.get(index) {
+        Some(Value::I32(v)) => *v as u32 as u64,
+        Some(Value::I64(v)) => *v as u64,
+        _ => 0,
+    }
+}
+
+fn bytes(caller: &Caller<'_, Host>, ptr: u64, len: u64) -> Vec<u8> {
+    let memory = caller
+        .get_export("memory")
+        .and_then(Extern::into_memory)
+        .unwrap();
+    let mut buf = vec![0; len as usize];
+    memory.read(caller, ptr as usize, &mut buf).unwrap();
+    buf
+}
+
+fn string(caller: &Caller<'_, Host>, ptr: u64, len: u64) -> String {
+    String::from_utf8(bytes(caller, ptr, len)).unwrap()
+}
+
+/// The host side of every function the auto splitter imports from the
+/// runtime. Returns the value of the function, if it has one.
+fn call(name: &str, mut caller: Caller<'_, Host>, params: &[Value]) -> Result<Option<u64>, Trap> {
+    let p = |index| int(params, index);
+    Ok(match name {
+        "runtime_set_tick_rate" => None,
+        "runtime_print_message" => {
+            let message = string(&caller, p(0), p(1));
+            caller.data_mut().messages.push(message);
+            None
+        }
+        "user_settings_add_bool" => {
+            let key = string(&caller, p(0), p(1));
+            let default = p(4) != 0;
+            caller.data_mut().settings.push((key, default));
+            Some(default as u64)
+        }
+        "timer_get_state" => Some(caller.data().timer_state as u64),
+        "timer_current_split_index" => {
+            let host = caller.data();
+            Some(if host.timer_state == NOT_RUNNING {
+                u64::MAX
+            } else {
+                host.splits.len() as u64
+            })
+        }
+        "timer_start" => {
+            caller.data_mut().timer_state = RUNNING;
+            None
+        }
+        "timer_split" => {
+            let host = caller.data_mut();
+            let split = host.messages.last().cloned().unwrap_or_default();
+            host.splits.push(split);
+            None
+        }
+        "timer_reset" => {
+            caller.data_mut().timer_state = NOT_RUNNING;
+            None
+        }
+        "timer_pause_game_time" | "timer_resume_game_time" => None,
+        "timer_set_game_time" => {
+            caller.data_mut().game_time = Some((p(0) as i64, p(1) as i32));
+            None
+        }
+        "timer_set_variable" => {
+            let key = string(&caller, p(0), p(1));
+            let value = string(&caller, p(2), p(3));
+            caller.data_mut().variables.insert(key, value);
+            None
+        }
+        "process_attach" => {
+            let name = string(&caller, p(0), p(1));
+            let host = caller.data_mut();
+            host.attached_to.push(name);
+            Some(host.emulator.is_some() as u64)
+        }
+        "process_detach" => None,
+        "process_is_open" => Some(caller.data().emulator.is_some() as u64),
+        "process_read" => {
+            let mut buf = vec![0; p(3) as usize];
+            let read = caller
+                .data()
+                .emulator
+                .as_ref()
+                .is_some_and(|e| e.read(p(1), &mut buf));
+            if read {
+                let memory = caller
+                    .get_export("memory")
+                    .and_then(Extern::into_memory)
+                    .unwrap();
+                memory.write(&mut caller, p(2) as usize, &buf).unwrap();
+            }
+            Some(read as u64)
+        }
+        "process_get_memory_range_count" => {
+            let emulator = caller.data().emulator.as_ref();
+            Some(emulator.map_or(0, |e| e.ranges().len() as u64))
+        }
+        "process_get_memory_range_address" | "process_get_memory_range_size" => {
+            let emulator = caller.data().emulator.as_ref();
+            let range = emulator.and_then(|e| e.ranges().get(p(1) as usize).copied());
+            range.map(|(base, range)| {
+                if name == "process_get_memory_range_address" {
+                    base
+                } else {
+                    range.len() as u64
+                }
+            })
+        }
+        "process_get_memory_range_flags" => Some(0),
+        _ => return Err(Trap::new(format!("The runtime doesn't provide {name}"))),
+    })
+}
+
+struct Harness {
+    store: Store<Host>,
+    update: TypedFunc<(), ()>,
+}
+
+impl Harness {
+    fn load() -> Option<Self> {
+        let path = env::var_os("AUTO_SPLITTER_WASM").map_or_else(
+            || {
+                PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                    .join("target/wasm32-unknown-unknown/release/minish_cap_auto_splitter.wasm")
+            },
+            PathBuf::from,
+        );
+        let wasm = match fs::read(&path) {
+            Ok(wasm) => wasm,
+            Err(_) if env::var_os("SKIP_WASM_TESTS").is_some() => {
+                eprintln!("Skipping, {} isn't built.", path.display());
+                return None;
+            }
+            Err(e) => panic!(
+                "Failed to read {}: {e}. Build it first or set SKIP_WASM_TESTS.",
+                path.display(),
+            ),
+        };
+
+        let engine = Engine::default();
+        let module = Module::new(&engine, &wasm[..]).unwrap();
+        let mut store = Store::new(&engine, Host::default());
+        let mut linker = Linker::new(&engine);
+
+        for import in module.imports() {
+            let Some(ty) = import.ty().func().cloned() else {
+                panic!("{} isn't a function", import.name());
+            };
+            let name = import.name().to_owned();
+            let result_types: Vec<ValueType> = ty.results().to_vec();
+            let func = Func::new(&mut store, ty, move |caller, params, results| {
+                for (result, &ty) in results.iter_mut().zip(&result_types) {
+                    *result = Value::default(ty);
+                }
+                if let (Some(value), Some(result)) =
+                    (call(&name, caller, params)?, results.first_mut())
+                {
+                    *result = match result_types[0] {
+                        ValueType::I64 => Value::I64(value as i64),
+                        _ => Value::I32(value as i32),
+                    };
+                }
+                Ok(())
+            });
+            linker.define(import.module(), import.name(), func).unwrap();
+        }
+
+        let instance = linker
+            .instantiate(&mut store, &module)
+            .unwrap()
+            .start(&mut store)
+            .unwrap();
+        let update = instance.get_typed_func(&store, "update").unwrap();
+        Some(Self { store, update })
+    }
+
+    fn host(&mut self) -> &mut Host {
+        self.store.data_mut()
+    }
+
+    fn emulator(&mut self) -> &mut FakeEmulator {
+        self.host().emulator.as_mut().unwrap()
+    }
+
+    fn tick(&mut self) {
+        if let Err(trap) = self.update.call(&mut self.store, ()) {
+            panic!("update trapped: {trap}");
+        }
+    }
+}
+
+#[test]
+fn settings_are_registered_once() {
+    let Some(mut harness) = Harness::load() else {
+        return;
+    };
+    harness.tick();
+    let settings = harness.host().settings.clone();
+    assert!(settings.contains(&("get_smiths_sword".to_owned(), true)));
+    assert!(settings.contains(&("record_memory_trace".to_owned(), false)));
+    for (index, (key, _)) in settings.iter().enumerate() {
+        assert!(
+            !settings[..index].iter().any(|(k, _)| k == key),
+            "{key} is registered twice",
+        );
+    }
+
+    harness.tick();
+    harness.tick();
+    assert_eq!(harness.host().settings.len(), settings.len());
+}
+
+#[test]
+fn registered_defaults_match_the_host_defaults() {
+    let Some(mut harness) = Harness::load() else {
+        return;
+    };
+    harness.tick();
+    // The host's settings print as `Settings { key: value, ... }`.
+    let host = format!("{:?}", Settings::default());
+    let host: Vec<(String, bool)> = host
+        .trim_start_matches("Settings {")
+        .trim_end_matches('}')
+        .split(',')
+        .map(|field| {
+            let (key, value) = field.split_once(':').unwrap();
+            (key.trim().to_owned(), value.trim().parse().unwrap())
+        })
+        .collect();
+    assert_eq!(harness.host().settings, host);
+}
+
+#[test]
+fn refuses_unsupported_roms() {
+    let Some(mut harness) = Harness::load() else {
+        return;
+    };
+    harness.host().emulator = Some(FakeEmulator::with_rom_header(b"AXVE"));
+    harness.tick();
+    let attached = harness.host().attached_to.len();
+    assert!(attached > 0);
+    for _ in 0..10 {
+        harness.tick();
+    }
+    let host = harness.host();
+    // The emulator is kept around rather than attached to on every tick.
+    assert_eq!(host.attached_to.len(), attached);
+    let complaints = host
+        .messages
+        .iter()
+        .filter(|m| m.starts_with("Unsupported ROM"))
+        .count();
+    assert_eq!(complaints, 1);
+    assert!(host.variables.is_empty());
+}
+
+#[test]
+fn attaches_as_bzmj_without_the_cartridge_header() {
+    let Some(mut harness) = Harness::load() else {
+        return;
+    };
+    // The ROM isn't mapped, so the game can't be identified.
+    harness.host().emulator = Some(FakeEmulator::new());
+    harness.emulator().write(0x200AF0E, &42u16.to_le_bytes());
+    for _ in 0..10 {
+        harness.tick();
+    }
+    let host = harness.host();
+    let notes = host
+        .messages
+        .iter()
+        .filter(|m| m.starts_with("Couldn't read the cartridge header"))
+        .count();
+    assert_eq!(notes, 1);
+    assert_eq!(host.variables.get("Rupees").map(|v| &**v), Some("42"));
+}
+
+#[test]
+fn runs_through_the_start_of_a_run() {
+    let Some(mut harness) = Harness::load() else {
+        return;
+    };
+    harness.host().emulator = Some(FakeEmulator::with_rom_header(b"BZMJ"));
+
+    let emulator = harness.emulator();
+    emulator.write(0x3001002, &[2]);
+    emulator.write(0x300100C, &1000u16.to_le_bytes());
+    emulator.write(0x3001E4E, &24i32.to_le_bytes());
+    emulator.write(0x300187A, &144i32.to_le_bytes());
+    emulator.write(0x200AF0E, &42u16.to_le_bytes());
+    harness.tick();
+    harness.tick();
+    assert_eq!(
+        harness.host().variables.get("Rupees").map(|v| &**v),
+        Some("42")
+    );
+    assert_eq!(harness.host().timer_state, NOT_RUNNING);
+
+    let emulator = harness.emulator();
+    emulator.write(0x300100C, &1001u16.to_le_bytes());
+    emulator.write(0x300187A, &150i32.to_le_bytes());
+    harness.tick();
+    assert_eq!(harness.host().timer_state, RUNNING);
+
+    // Smith's Sword, a second later.
+    let emulator = harness.emulator();
+    emulator.write(0x3001002, &[3]);
+    emulator.write(0x300100C, &1061u16.to_le_bytes());
+    emulator.write(0x2002B32, &[0x04]);
+    harness.tick();
+    let host = harness.host();
+    assert_eq!(host.splits, ["Get Smith's Sword"]);
+    assert_eq!(host.game_time, Some((1, 0)));
+}